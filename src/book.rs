@@ -0,0 +1,258 @@
+use std::fs::File;
+use std::io::{Read, Write};
+
+use rand::{Isaac64Rng, Rng};
+
+use {Board, Color, Move, Piece, Square};
+
+// How a move is chosen when the book has several for a position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    Weighted,
+    Best,
+}
+
+impl Selection {
+    pub fn from_name(name: &str) -> Option<Selection> {
+        match name {
+            "weighted" => Some(Selection::Weighted),
+            "best" => Some(Selection::Best),
+            _ => None,
+        }
+    }
+}
+
+// One 16 byte record of a Polyglot `.bin` file, all fields big-endian. The last
+// four bytes hold learning data, which is not used.
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    key: u64,
+    cmove: u16,
+    weight: u16,
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &byte| acc << 8 | byte as u64)
+}
+
+fn write_be(value: u64, size: usize) -> Vec<u8> {
+    (0..size).rev().map(|byte| (value >> (byte * 8)) as u8).collect()
+}
+
+pub fn encode_move(from: &Square, to: &Square, promotion: Option<Piece>) -> u16 {
+    let square = |square: &Square| {
+        let (i, j) = square.indexes();
+        (j * 8 + i) as u16
+    };
+    let promotion = match promotion {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    promotion << 12 | square(from) << 6 | square(to)
+}
+
+#[derive(Debug)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn open(path: &str) -> Result<Book, String> {
+        let mut file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes).map_err(|e| format!("{}: {}", path, e))?;
+        if bytes.len() % 16 != 0 {
+            return Err(format!("{}: not a Polyglot book, size is not a multiple of 16", path));
+        }
+
+        let entries = bytes.chunks(16)
+            .map(|record| {
+                Entry {
+                    key: read_be(&record[0..8]),
+                    cmove: read_be(&record[8..10]) as u16,
+                    weight: read_be(&record[10..12]) as u16,
+                }
+            })
+            .collect::<Vec<Entry>>();
+        Ok(Book::from_entries(entries))
+    }
+
+    // (position key, Polyglot move, weight) triples in any order
+    pub fn new(moves: Vec<(u64, u16, u16)>) -> Book {
+        Book::from_entries(moves.into_iter()
+            .map(|(key, cmove, weight)| {
+                Entry {
+                    key: key,
+                    cmove: cmove,
+                    weight: weight,
+                }
+            })
+            .collect())
+    }
+
+    fn from_entries(mut entries: Vec<Entry>) -> Book {
+        // lookups need the entries of a position together and sorted by key,
+        // within a position the heaviest move comes first as in Polyglot's tools
+        entries.sort_by(|a, b| (a.key, b.weight).cmp(&(b.key, a.weight)));
+        Book { entries: entries }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut bytes = vec![];
+        for entry in &self.entries {
+            bytes.extend(write_be(entry.key, 8));
+            bytes.extend(write_be(entry.cmove as u64, 2));
+            bytes.extend(write_be(entry.weight as u64, 2));
+            bytes.extend(write_be(0, 4));
+        }
+
+        let mut file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        file.write_all(&bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn decode(board: &Board, turn: Color, cmove: u16) -> Option<Move> {
+        let square = |bits: u16| {
+            Square::from_indexes((bits & 7) as usize, (bits >> 3 & 7) as usize)
+        };
+        let from = square(cmove >> 6);
+        let to = square(cmove);
+
+        // the board has no promotion or castling, which Polyglot writes as the
+        // king taking its own rook
+        if cmove >> 12 != 0 || board.get(&to) == (turn, Piece::Rook) {
+            return None;
+        }
+        Some((from, to))
+    }
+
+    // Book moves that can be played on `board`, with their weights
+    pub fn moves(&self,
+                 board: &Board,
+                 turn: Color,
+                 en_passant: Option<Square>)
+                 -> Vec<(Move, u16)> {
+        let key = board.polyglot_key(turn, en_passant);
+        let (mut low, mut high) = (0, self.entries.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.entries[middle].key < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let legal_moves = board.legal_moves(turn);
+        self.entries[low..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                Book::decode(board, turn, entry.cmove).map(|cmove| (cmove, entry.weight))
+            })
+            .filter(|&(cmove, weight)| weight > 0 && legal_moves.contains(&cmove))
+            .collect()
+    }
+
+    pub fn pick(&self,
+                board: &Board,
+                turn: Color,
+                en_passant: Option<Square>,
+                selection: Selection,
+                rng: &mut Isaac64Rng)
+                -> Option<Move> {
+        let moves = self.moves(board, turn, en_passant);
+        match selection {
+            Selection::Best => moves.iter().max_by_key(|&&(_, weight)| weight).map(|&(m, _)| m),
+            Selection::Weighted => {
+                let total = moves.iter().fold(0, |acc, &(_, weight)| acc + weight as u32);
+                if total == 0 {
+                    return None;
+                }
+                let mut choice = rng.gen_range(0, total);
+                for &(cmove, weight) in &moves {
+                    if choice < weight as u32 {
+                        return Some(cmove);
+                    }
+                    choice -= weight as u32;
+                }
+                unreachable!()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use rand::{Isaac64Rng, SeedableRng};
+
+    use {Board, Color, Piece, Square};
+    use super::{encode_move, Book, Selection};
+
+    fn square(name: &str) -> Square {
+        let name = name.as_bytes();
+        Square::new(name[0] as char, name[1] - b'0')
+    }
+
+    // Plays `moves` from the start, given as pairs of squares, then keys the
+    // position with the en passant square left by the last one
+    fn key_after(moves: &[(&str, &str)], en_passant: Option<&str>) -> u64 {
+        let mut board = Board::new();
+        for &(from, to) in moves {
+            board = board.exec_move(&square(from), &square(to));
+        }
+        let turn = if moves.len() % 2 == 0 { Color::White } else { Color::Black };
+        board.polyglot_key(turn, en_passant.map(square))
+    }
+
+    // The example keys given with the Polyglot book format
+    #[test]
+    fn polyglot_keys() {
+        let moves = [("e2", "e4"), ("d7", "d5"), ("e4", "e5"), ("f7", "f5"), ("e1", "e2"),
+                     ("e8", "f7")];
+        assert_eq!(key_after(&[], None), 0x463b96181691fc9c);
+        assert_eq!(key_after(&moves[..1], Some("e3")), 0x823c9b50fd114196);
+        assert_eq!(key_after(&moves[..2], Some("d6")), 0x0756b94461c50fb0);
+        assert_eq!(key_after(&moves[..3], None), 0x662fafb965db29d4);
+        assert_eq!(key_after(&moves[..4], Some("f6")), 0x22a48b5a8e47ff78);
+        assert_eq!(key_after(&moves[..5], None), 0x652a607ca3f242c1);
+        assert_eq!(key_after(&moves[..6], None), 0x00fdd303c946bdd9);
+
+        let moves = [("a2", "a4"), ("b7", "b5"), ("h2", "h4"), ("b5", "b4"), ("c2", "c4")];
+        assert_eq!(key_after(&moves, Some("c3")), 0x3c8123ea7b067637);
+    }
+
+    #[test]
+    fn moves_of_a_position() {
+        let start = Board::new().polyglot_key(Color::White, None);
+        let e4 = encode_move(&square("e2"), &square("e4"), None);
+        let d4 = encode_move(&square("d2"), &square("d4"), None);
+        assert_eq!(e4, 0x031c);
+        assert_eq!(encode_move(&square("a7"), &square("a8"), Some(Piece::Queen)), 0x4c38);
+
+        let book = Book::new(vec![(start, d4, 1), (start, e4, 3), (start + 1, e4, 5)]);
+        let moves = book.moves(&Board::new(), Color::White, None);
+        assert_eq!(moves,
+                   vec![((square("e2"), square("e4")), 3), ((square("d2"), square("d4")), 1)]);
+
+        let mut rng = Isaac64Rng::from_seed(&[1]);
+        assert_eq!(book.pick(&Board::new(), Color::White, None, Selection::Best, &mut rng),
+                   Some((square("e2"), square("e4"))));
+    }
+
+    #[test]
+    fn save_and_open() {
+        let start = Board::new().polyglot_key(Color::White, None);
+        let e4 = encode_move(&square("e2"), &square("e4"), None);
+        let path = env::temp_dir().join("chess-book-save-and-open.bin");
+        let path = path.to_str().unwrap();
+        Book::new(vec![(start, e4, 7)]).save(path).unwrap();
+
+        let moves = Book::open(path).unwrap().moves(&Board::new(), Color::White, None);
+        assert_eq!(moves, vec![((square("e2"), square("e4")), 7)]);
+    }
+}
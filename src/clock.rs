@@ -0,0 +1,290 @@
+use std::fmt;
+
+use time;
+
+use Color;
+
+// What a player gets back for each move, in ms
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bonus {
+    None,
+    // added after every move
+    Fischer(u64),
+    // the time the move took is given back, up to this much
+    Bronstein(u64),
+    // the clock only starts running after this much of each move
+    Delay(u64),
+}
+
+// `moves` moves in `time_ms`, or the rest of the game when `moves` is None
+#[derive(Clone, Copy, Debug)]
+pub struct Stage {
+    pub moves: Option<usize>,
+    pub time_ms: u64,
+    pub bonus: Bonus,
+}
+
+#[derive(Clone, Debug)]
+pub struct TimeControl {
+    stages: Vec<Stage>,
+}
+
+fn parse_number(text: &str, scale: f64) -> Result<u64, String> {
+    match text.parse::<f64>() {
+        Ok(value) if value >= 0.0 => Ok((value * scale) as u64),
+        _ => Err(format!("invalid time '{}'", text)),
+    }
+}
+
+impl TimeControl {
+    // Stages separated by `:`, each `[moves/]minutes` followed by `+seconds` of
+    // increment, `d` and the seconds of a simple delay, or `b` and those of a
+    // Bronstein delay: `5+3`, `40/90+30:30+30`, `15d5`. A last stage with a
+    // move count repeats.
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let mut stages = vec![];
+        for stage in text.split(':') {
+            let (moves, rest) = match stage.find('/') {
+                Some(slash) => {
+                    let moves = stage[..slash]
+                        .parse()
+                        .map_err(|_| format!("invalid move count in '{}'", stage))?;
+                    (Some(moves), &stage[slash + 1..])
+                }
+                None => (None, stage),
+            };
+            let (time, bonus) = match rest.find(['+', 'd', 'b']) {
+                Some(index) => {
+                    let seconds = parse_number(&rest[index + 1..], 1000.0)?;
+                    let bonus = match &rest[index..index + 1] {
+                        "+" => Bonus::Fischer(seconds),
+                        "b" => Bonus::Bronstein(seconds),
+                        _ => Bonus::Delay(seconds),
+                    };
+                    (&rest[..index], bonus)
+                }
+                None => (rest, Bonus::None),
+            };
+            stages.push(Stage {
+                moves: moves,
+                time_ms: parse_number(time, 60000.0)?,
+                bonus: bonus,
+            });
+        }
+        if stages.iter().rev().skip(1).any(|stage| stage.moves.is_none()) {
+            return Err(format!("only the last stage of '{}' can be without a move count",
+                               text));
+        }
+        Ok(TimeControl { stages: stages })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if index > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{}/", moves)?;
+            }
+            write!(f, "{}", stage.time_ms as f64 / 60000.0)?;
+            match stage.bonus {
+                Bonus::None => Ok(()),
+                Bonus::Fischer(ms) => write!(f, "+{}", ms as f64 / 1000.0),
+                Bonus::Bronstein(ms) => write!(f, "b{}", ms as f64 / 1000.0),
+                Bonus::Delay(ms) => write!(f, "d{}", ms as f64 / 1000.0),
+            }?;
+        }
+        Ok(())
+    }
+}
+
+// Time for the next move: an even share of what is left over the moves still
+// to play in the stage, 30 when it has no end, plus most of the increment.
+// Some time is always held back for the moves to come.
+pub fn budget(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<usize>) -> u64 {
+    let moves = moves_to_go.unwrap_or(30).max(1) as u64;
+    let reserve = (remaining_ms / 10).min(1000);
+    (remaining_ms / moves + increment_ms * 3 / 4).min(remaining_ms - reserve).max(1)
+}
+
+// Both players' clocks during a game, indexed by color
+#[derive(Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    remaining: [u64; 2],
+    stage: [usize; 2],
+    // moves made in the current stage
+    moves: [usize; 2],
+    // when the running clock was started, in ns
+    started: Option<u64>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        let time_ms = control.stages[0].time_ms;
+        Clock {
+            control: control,
+            remaining: [time_ms; 2],
+            stage: [0; 2],
+            moves: [0; 2],
+            started: None,
+        }
+    }
+
+    fn current(&self, color: Color) -> Stage {
+        let last = self.control.stages.len() - 1;
+        self.control.stages[self.stage[color as usize].min(last)]
+    }
+
+    pub fn remaining(&self, color: Color) -> u64 {
+        self.remaining[color as usize]
+    }
+
+    pub fn moves_to_go(&self, color: Color) -> Option<usize> {
+        self.current(color).moves.map(|moves| moves - self.moves[color as usize])
+    }
+
+    // What every move is sure to get back, whichever kind of bonus it is
+    pub fn increment(&self, color: Color) -> u64 {
+        match self.current(color).bonus {
+            Bonus::None => 0,
+            Bonus::Fischer(ms) | Bonus::Bronstein(ms) | Bonus::Delay(ms) => ms,
+        }
+    }
+
+    pub fn budget(&self, color: Color) -> u64 {
+        budget(self.remaining(color), self.increment(color), self.moves_to_go(color))
+    }
+
+    pub fn start(&mut self) {
+        self.started = Some(time::precise_time_ns());
+    }
+
+    // Stops the clock after `color` moved, false if its flag fell first
+    pub fn stop(&mut self, color: Color) -> bool {
+        let elapsed = self.started
+            .take()
+            .map_or(0, |start| (time::precise_time_ns() - start) / 1000000);
+        let stage = self.current(color);
+        let side = color as usize;
+
+        let used = match stage.bonus {
+            Bonus::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        };
+        if used > self.remaining[side] {
+            self.remaining[side] = 0;
+            return false;
+        }
+        self.remaining[side] -= used;
+        self.remaining[side] += match stage.bonus {
+            Bonus::Fischer(increment) => increment,
+            Bonus::Bronstein(delay) => used.min(delay),
+            _ => 0,
+        };
+
+        self.moves[side] += 1;
+        if Some(self.moves[side]) == stage.moves {
+            self.stage[side] += 1;
+            self.moves[side] = 0;
+            self.remaining[side] += self.current(color).time_ms;
+        }
+        true
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = |ms: u64| format!("{}:{:04.1}", ms / 60000, (ms % 60000) as f64 / 1000.0);
+        write!(f,
+               "White {}  Black {}",
+               format(self.remaining(Color::White)),
+               format(self.remaining(Color::Black)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Color;
+    use super::{budget, Bonus, Clock, TimeControl};
+
+    fn clock(text: &str) -> Clock {
+        Clock::new(TimeControl::parse(text).unwrap())
+    }
+
+    #[test]
+    fn parse() {
+        let control = TimeControl::parse("40/90+30:30+30").unwrap();
+        assert_eq!(control.stages.len(), 2);
+        assert_eq!(control.stages[0].moves, Some(40));
+        assert_eq!(control.stages[0].time_ms, 90 * 60000);
+        assert_eq!(control.stages[0].bonus, Bonus::Fischer(30000));
+        assert_eq!(control.stages[1].moves, None);
+
+        assert_eq!(TimeControl::parse("5").unwrap().stages[0].bonus, Bonus::None);
+        assert_eq!(TimeControl::parse("15d5").unwrap().stages[0].bonus, Bonus::Delay(5000));
+        assert_eq!(TimeControl::parse("3b2").unwrap().stages[0].bonus, Bonus::Bronstein(2000));
+        assert_eq!(TimeControl::parse("0.5+0.1").unwrap().stages[0].time_ms, 30000);
+
+        for text in &["40/90+30:30+30", "5", "15d5", "3b2", "0.5+0.1", "40/120:20/60:15"] {
+            assert_eq!(TimeControl::parse(text).unwrap().to_string(), *text);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(TimeControl::parse("").is_err());
+        assert!(TimeControl::parse("five").is_err());
+        assert!(TimeControl::parse("x/5").is_err());
+        assert!(TimeControl::parse("5+-1").is_err());
+        assert!(TimeControl::parse("5:40/90").is_err());
+    }
+
+    #[test]
+    fn budgets() {
+        // sudden death spreads the time over 30 moves
+        assert_eq!(clock("5").budget(Color::White), 10000);
+        // every kind of bonus adds three quarters of itself
+        assert_eq!(clock("5+3").budget(Color::White), 12250);
+        assert_eq!(clock("5b3").budget(Color::White), 12250);
+        assert_eq!(clock("5d3").budget(Color::White), 12250);
+        // a stage with a move count spreads its time over the moves left in it
+        assert_eq!(clock("40/90+30:30+30").budget(Color::White), 157500);
+        // a tenth of what is left, at most a second, is held back
+        assert_eq!(budget(100, 5000, None), 90);
+        assert_eq!(budget(0, 0, Some(1)), 1);
+    }
+
+    #[test]
+    fn bonuses() {
+        let mut fischer = clock("5+3");
+        assert!(fischer.stop(Color::White));
+        assert_eq!(fischer.remaining(Color::White), 303000);
+        assert_eq!(fischer.remaining(Color::Black), 300000);
+
+        // only the time actually used comes back, here none
+        let mut bronstein = clock("5b3");
+        assert!(bronstein.stop(Color::White));
+        assert_eq!(bronstein.remaining(Color::White), 300000);
+
+        let mut delay = clock("5d3");
+        assert!(delay.stop(Color::White));
+        assert_eq!(delay.remaining(Color::White), 300000);
+    }
+
+    #[test]
+    fn stages() {
+        let mut clock = clock("2/1+1:1");
+        assert_eq!(clock.moves_to_go(Color::White), Some(2));
+        assert!(clock.stop(Color::White));
+        assert_eq!(clock.moves_to_go(Color::White), Some(1));
+        assert!(clock.stop(Color::White));
+        // the next stage's time is added and its own bonus applies
+        assert_eq!(clock.remaining(Color::White), 60000 + 2000 + 60000);
+        assert_eq!(clock.moves_to_go(Color::White), None);
+        assert_eq!(clock.increment(Color::White), 0);
+        assert_eq!(clock.moves_to_go(Color::Black), Some(2));
+    }
+}
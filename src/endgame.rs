@@ -0,0 +1,267 @@
+use {Board, Color, Piece};
+use kpk;
+use params::Params;
+
+// Above any positional score but well clear of the mate scores
+pub const KNOWN_WIN: i32 = 10000;
+
+// Scale factors are out of this, applied to the side the evaluation favours
+pub const SCALE_NORMAL: i32 = 64;
+
+// What is known about a position from its material alone
+pub enum Known {
+    // replaces the evaluation, centipawns from White's point of view
+    Exact(i32),
+    // by the color the evaluation favours
+    Scale([i32; 2]),
+}
+
+struct Material {
+    counts: [[usize; 7]; 2],
+    kings: [(usize, usize); 2],
+    // square color of each side's last bishop found
+    bishops: [usize; 2],
+}
+
+impl Material {
+    fn new(board: &Board) -> Option<Material> {
+        let mut material = Material {
+            counts: [[0; 7]; 2],
+            kings: [(0, 0); 2],
+            bishops: [0; 2],
+        };
+        for (i, col) in board.squares.iter().enumerate() {
+            for (j, &(color, piece)) in col.iter().enumerate() {
+                material.counts[color as usize][piece as usize] += 1;
+                match piece {
+                    Piece::King => material.kings[color as usize] = (i, j),
+                    Piece::Bishop => material.bishops[color as usize] = (i + j) % 2,
+                    _ => (),
+                }
+            }
+        }
+        if material.count(Color::White, Piece::King) != 1 ||
+           material.count(Color::Black, Piece::King) != 1 {
+            return None;
+        }
+        Some(material)
+    }
+
+    fn count(&self, color: Color, piece: Piece) -> usize {
+        self.counts[color as usize][piece as usize]
+    }
+
+    fn pieces(&self, color: Color) -> usize {
+        [Piece::Bishop, Piece::Knight, Piece::Rook, Piece::Queen]
+            .iter()
+            .map(|&piece| self.count(color, piece))
+            .sum()
+    }
+
+    fn non_pawn_value(&self, params: &Params, color: Color) -> i32 {
+        [Piece::Bishop, Piece::Knight, Piece::Rook, Piece::Queen]
+            .iter()
+            .map(|&piece| self.count(color, piece) as i32 * params.value(piece))
+            .sum()
+    }
+
+    fn value(&self, params: &Params, color: Color) -> i32 {
+        self.non_pawn_value(params, color) +
+        self.count(color, Piece::Pawn) as i32 * params.value(Piece::Pawn)
+    }
+}
+
+fn distance(a: (usize, usize), b: (usize, usize)) -> i32 {
+    let files = a.0 as i32 - b.0 as i32;
+    let ranks = a.1 as i32 - b.1 as i32;
+    files.abs().max(ranks.abs())
+}
+
+// From 0 in the centre to 120 in the corners
+fn push_to_edge(square: (usize, usize)) -> i32 {
+    let (i, j) = (square.0 as i32, square.1 as i32);
+    10 * ((2 * i - 7).abs() + (2 * j - 7).abs() - 2)
+}
+
+// Bringing the kings together takes away the lone king's squares
+fn push_close(a: (usize, usize), b: (usize, usize)) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+fn for_color(color: Color, score: i32) -> i32 {
+    match color {
+        Color::Black => -score,
+        Color::White => score,
+    }
+}
+
+// Enough force to mate a bare king without help from pawns
+fn mating_material(material: &Material, color: Color) -> bool {
+    material.count(color, Piece::Queen) > 0 || material.count(color, Piece::Rook) > 0 ||
+    (material.count(color, Piece::Bishop) > 0 && material.count(color, Piece::Knight) > 0) ||
+    material.count(color, Piece::Bishop) > 1
+}
+
+fn kxk(params: &Params, material: &Material, strong: Color) -> i32 {
+    let weak = strong.other();
+    let strong_king = material.kings[strong as usize];
+    let weak_king = material.kings[weak as usize];
+    KNOWN_WIN + material.value(params, strong) + push_to_edge(weak_king) +
+    push_close(strong_king, weak_king)
+}
+
+// Mate is only possible in the two corners of the bishop's color
+fn kbnk(params: &Params, material: &Material, strong: Color) -> i32 {
+    let weak = strong.other();
+    let strong_king = material.kings[strong as usize];
+    let weak_king = material.kings[weak as usize];
+    let corners = if material.bishops[strong as usize] == 0 {
+        [(0, 0), (7, 7)]
+    } else {
+        [(7, 0), (0, 7)]
+    };
+    let corner = distance(weak_king, corners[0]).min(distance(weak_king, corners[1]));
+    KNOWN_WIN + material.value(params, strong) + push_to_edge(weak_king) / 4 +
+    40 * (7 - corner) + push_close(strong_king, weak_king)
+}
+
+fn kpk(board: &Board, material: &Material, strong: Color, turn: Color) -> Option<i32> {
+    let weak = strong.other();
+    let pawn = board.squares
+        .iter()
+        .enumerate()
+        .flat_map(|(i, col)| col.iter().enumerate().map(move |(j, &square)| (i, j, square)))
+        .find(|&(_, _, square)| square == (strong, Piece::Pawn))
+        .map(|(i, j, _)| (i, j))
+        .unwrap();
+    // seen from the pawn's side, moving up the board
+    let relative = |(i, j): (usize, usize)| match strong {
+        Color::White => (i, j),
+        Color::Black => (i, 7 - j),
+    };
+    let pawn = relative(pawn);
+
+    let wins = kpk::probe(turn == strong,
+                          relative(material.kings[strong as usize]),
+                          relative(material.kings[weak as usize]),
+                          pawn)?;
+    if wins {
+        Some(KNOWN_WIN + 100 + 10 * pawn.1 as i32)
+    } else {
+        Some(0)
+    }
+}
+
+// Material is weighed with `params`, so tuned values carry over
+pub fn probe(board: &Board, turn: Color, params: &Params) -> Option<Known> {
+    let material = Material::new(board)?;
+
+    for &strong in &[Color::White, Color::Black] {
+        let weak = strong.other();
+        if material.pieces(weak) + material.count(weak, Piece::Pawn) > 0 {
+            continue;
+        }
+        let pawns = material.count(strong, Piece::Pawn);
+        let pieces = material.pieces(strong);
+
+        if pawns == 0 && material.count(strong, Piece::Bishop) == 1 &&
+           material.count(strong, Piece::Knight) == 1 && pieces == 2 {
+            return Some(Known::Exact(for_color(strong, kbnk(params, &material, strong))));
+        }
+        if mating_material(&material, strong) {
+            return Some(Known::Exact(for_color(strong, kxk(params, &material, strong))));
+        }
+        if pawns == 1 && pieces == 0 {
+            // a pawn left on its last rank is not something the table knows
+            if let Some(score) = kpk(board, &material, strong, turn) {
+                return Some(Known::Exact(for_color(strong, score)));
+            }
+        }
+        if pawns == 0 {
+            // a lone minor piece or two knights cannot force mate
+            return Some(Known::Exact(0));
+        }
+    }
+
+    let (minor, rook) = (params.value(Piece::Bishop), params.value(Piece::Rook));
+    let mut scale = [SCALE_NORMAL; 2];
+    for &strong in &[Color::White, Color::Black] {
+        let (own, other) = (material.non_pawn_value(params, strong),
+                            material.non_pawn_value(params, strong.other()));
+        // without pawns, a minor piece more is seldom enough to win
+        if material.count(strong, Piece::Pawn) == 0 && own - other <= minor {
+            scale[strong as usize] = if own < rook {
+                0
+            } else if other <= minor {
+                4
+            } else {
+                14
+            };
+        }
+    }
+
+    // bishops of opposite colors with only pawns left besides
+    let only_bishop = |color| {
+        material.pieces(color) == 1 && material.count(color, Piece::Bishop) == 1
+    };
+    if only_bishop(Color::White) && only_bishop(Color::Black) &&
+       material.bishops[0] != material.bishops[1] {
+        for factor in &mut scale {
+            *factor = (*factor).min(SCALE_NORMAL / 2);
+        }
+    }
+
+    if scale == [SCALE_NORMAL; 2] {
+        None
+    } else {
+        Some(Known::Scale(scale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{probe, Known, KNOWN_WIN};
+    use Board;
+    use params::Params;
+
+    fn exact(fen: &str) -> Option<i32> {
+        let (board, turn) = Board::from_fen(fen).unwrap();
+        match probe(&board, turn, &Params::default()) {
+            Some(Known::Exact(score)) => Some(score),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn kpk() {
+        assert_eq!(exact("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(0));
+        assert!(exact("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1").unwrap() > KNOWN_WIN);
+        assert!(exact("8/4k3/8/4K3/4p3/8/8/8 b - - 0 1").unwrap() < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn kpk_pawn_on_edge_rank() {
+        // left there by moves that do not promote; not a position the table covers
+        assert_eq!(exact("4P3/8/8/8/8/2K5/8/k7 w - - 0 1"), None);
+        assert_eq!(exact("k7/8/8/8/8/2K5/8/4P3 b - - 0 1"), None);
+        assert_eq!(exact("K7/8/8/8/8/2k5/8/4p3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn lone_king_driven_to_the_edge() {
+        // the kings stay two squares apart so only the edge term changes
+        for piece in &["Q", "R"] {
+            let centre = exact(&format!("8/8/8/3k4/8/3K4/8/{}7 w - - 0 1", piece)).unwrap();
+            let edge = exact(&format!("3k4/8/3K4/8/8/8/8/{}7 w - - 0 1", piece)).unwrap();
+            let corner = exact(&format!("7k/8/7K/8/8/8/8/{}7 w - - 0 1", piece)).unwrap();
+            assert!(centre > KNOWN_WIN);
+            assert!(edge > centre);
+            assert!(corner > edge);
+
+            let black = exact(&format!("8/8/8/3K4/8/3k4/8/{}7 w - - 0 1",
+                                       piece.to_lowercase()))
+                .unwrap();
+            assert_eq!(black, -centre);
+        }
+    }
+}
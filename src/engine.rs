@@ -0,0 +1,228 @@
+use std::sync::Arc;
+
+use futures_cpupool::CpuPool;
+use rand::{self, Isaac64Rng, Rng, SeedableRng};
+use time;
+
+use {move_string, next_move, sample_lines, Board, Color, GameTreeNode, Move};
+use book::{Book, Selection};
+use clock::Clock;
+use eval::Evaluator;
+use params::Params;
+use search::{SearchInfo, SearchOptions};
+use smp::LazySmp;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    AlphaBeta,
+    Sampling,
+}
+
+impl Algorithm {
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name {
+            "alphabeta" => Some(Algorithm::AlphaBeta),
+            "sampling" => Some(Algorithm::Sampling),
+            _ => None,
+        }
+    }
+
+    fn default_depth(&self) -> usize {
+        match *self {
+            Algorithm::AlphaBeta => 4,
+            Algorithm::Sampling => 5,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    pub algorithm: Algorithm,
+    pub depth: usize,
+    pub threads: usize,
+    // number of root moves reported by `Engine::analyse`
+    pub lines: usize,
+    // every random choice of the engine derives from this
+    pub seed: u64,
+    // opening moves are taken from here while the position is in the book
+    pub book: Option<Arc<Book>>,
+    pub book_selection: Selection,
+    pub params: Params,
+    pub search: SearchOptions,
+    // no progress output while choosing a move
+    pub quiet: bool,
+}
+
+impl EngineConfig {
+    pub fn new(algorithm: Algorithm, params: Params) -> EngineConfig {
+        EngineConfig {
+            algorithm: algorithm,
+            depth: algorithm.default_depth(),
+            threads: 1,
+            lines: 1,
+            seed: rand::thread_rng().gen(),
+            book: None,
+            book_selection: Selection::Weighted,
+            params: params,
+            search: SearchOptions::default(),
+            quiet: false,
+        }
+    }
+}
+
+pub struct Engine {
+    config: EngineConfig,
+    evaluator: Evaluator,
+    // Sampling keeps its tree between moves, alpha-beta its hash table
+    tree: Option<GameTreeNode>,
+    rng: Isaac64Rng,
+    // shared with the search, sampling runs `config.threads` tasks on it
+    pool: CpuPool,
+    search: LazySmp,
+    // for the side to move, from the last alpha-beta search for a move
+    score: Option<i32>,
+}
+
+impl Engine {
+    // `pool` runs the helper threads of the search, engines can share one
+    pub fn new(config: EngineConfig, pool: CpuPool) -> Engine {
+        let mut search = LazySmp::new(config.threads,
+                                      config.params.clone(),
+                                      config.search,
+                                      pool.clone());
+        search.set_quiet(config.quiet);
+        Engine {
+            evaluator: Evaluator::with_params(config.params.clone()),
+            tree: None,
+            rng: Isaac64Rng::from_seed(&[config.seed]),
+            pool: pool,
+            search: search,
+            score: None,
+            config: config,
+        }
+    }
+
+    // The engine is only given positions, so en passant never counts towards
+    // the book key
+    fn book_move(&mut self, board: &Board, turn: Color) -> Option<Move> {
+        match self.config.book {
+            Some(ref book) => {
+                book.pick(board, turn, None, self.config.book_selection, &mut self.rng)
+            }
+            None => None,
+        }
+    }
+
+    // With a clock, alpha-beta searches for the time it budgets instead of to
+    // a fixed depth
+    pub fn next_move(&mut self, board: Board, turn: Color, clock: Option<&Clock>) -> Option<Move> {
+        self.score = None;
+        if let Some(cmove) = self.book_move(&board, turn) {
+            if !self.config.quiet {
+                println!("book: {}", move_string(&cmove));
+            }
+            return Some(cmove);
+        }
+
+        match self.config.algorithm {
+            Algorithm::AlphaBeta => {
+                self.search.reset();
+                let mut lines = match clock {
+                    Some(clock) => self.search.search_for(&board, turn, 1, clock.budget(turn)),
+                    None => self.search.search(&board, turn, self.config.depth, 1),
+                };
+                // out of time before the first iteration finished
+                if lines.is_empty() {
+                    self.search.reset();
+                    lines = self.search.search(&board, turn, 1, 1);
+                }
+                self.score = lines.first().map(|line| line.score);
+                lines.first().and_then(|line| line.pv.first().cloned())
+            }
+            Algorithm::Sampling => {
+                next_move(&mut self.tree,
+                          board,
+                          turn,
+                          self.config.depth,
+                          &mut self.evaluator,
+                          &mut self.rng,
+                          (&self.pool, self.config.threads),
+                          self.config.quiet)
+            }
+        }
+    }
+
+    // Centipawns for the side that moved last, when its search gave a score
+    pub fn score(&self) -> Option<i32> {
+        self.score
+    }
+
+    // The sampling tree kept from the last analysis or move
+    pub fn tree(&self) -> Option<&GameTreeNode> {
+        self.tree.as_ref()
+    }
+
+    // The best `config.lines` root moves, each with its score for the side to
+    // move and its principal variation
+    pub fn analyse(&mut self, board: Board, turn: Color) -> Vec<SearchInfo> {
+        match self.config.algorithm {
+            Algorithm::AlphaBeta => {
+                self.search.reset();
+                self.search.search(&board, turn, self.config.depth, self.config.lines)
+            }
+            Algorithm::Sampling => {
+                let start = time::precise_time_ns();
+                let mut root = GameTreeNode::new(board, turn, 64, &mut self.rng);
+                root.exec_random_moves(self.config.depth,
+                                       &mut self.rng,
+                                       Some((&self.pool, self.config.threads)));
+                let time_ms = (time::precise_time_ns() - start) / 1000000;
+
+                let lines = sample_lines(&root,
+                                         self.config.depth,
+                                         self.config.lines,
+                                         time_ms,
+                                         &mut self.evaluator);
+                // kept for inspection, and reused if the engine is asked to move here
+                self.tree = Some(root);
+                lines
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_cpupool::CpuPool;
+
+    use {Board, Color};
+    use params::Params;
+    use super::{Algorithm, Engine, EngineConfig};
+
+    fn sampling(threads: usize) -> Engine {
+        let mut config = EngineConfig::new(Algorithm::Sampling, Params::default());
+        config.depth = 3;
+        config.threads = threads;
+        config.lines = 3;
+        config.seed = 7;
+        config.quiet = true;
+        Engine::new(config, CpuPool::new(4))
+    }
+
+    #[test]
+    fn sampling_does_not_depend_on_threads() {
+        let (board, turn) = (Board::new(), Color::White);
+        let lines = |threads| {
+            sampling(threads)
+                .analyse(board, turn)
+                .into_iter()
+                .map(|line| (line.score, line.pv))
+                .collect::<Vec<_>>()
+        };
+        let serial = lines(1);
+        assert_eq!(serial.len(), 3);
+        assert_eq!(lines(3), serial);
+        assert_eq!(sampling(4).next_move(board, turn, None),
+                   sampling(1).next_move(board, turn, None));
+    }
+}
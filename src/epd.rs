@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use {Board, Color, Move};
+use book;
+use pgn::Replay;
+use search::MAX_DEPTH;
+use smp::LazySmp;
+
+// How long each position is searched
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    Depth(usize),
+    Time(u64),
+}
+
+// A test position: the four FEN fields followed by `opcode operands;` operations
+pub struct Position {
+    pub id: String,
+    pub board: Board,
+    pub turn: Color,
+    // with the castling and en passant rights the SAN moves are read against
+    replay: Replay,
+    // SAN moves the engine should play (`bm`) or avoid (`am`)
+    pub best: Vec<String>,
+    pub avoid: Vec<String>,
+    // STS lists partial credit here as `c0 "f5=10, Be5+=2"`
+    pub comment: Option<String>,
+}
+
+// Splits the operations on `;`, leaving quoted operands whole
+fn operations(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut operations = vec![];
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' | ' ' | '\t' if !quoted => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+                if c == ';' && !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, tokens.clone()));
+                    tokens.clear();
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    if !tokens.is_empty() {
+        let opcode = tokens.remove(0);
+        operations.push((opcode, tokens));
+    }
+    operations
+}
+
+impl Position {
+    pub fn parse(line: &str) -> Result<Position, String> {
+        let fields = line.split_whitespace().take(4).collect::<Vec<&str>>();
+        if fields.len() < 4 {
+            return Err("expected the four FEN fields of an EPD record".to_string());
+        }
+        let replay = Replay::from_fen(&fields.join(" "))?;
+
+        // the operations start after the fourth field
+        let mut rest = line.trim_start();
+        for _ in 0..4 {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            rest = rest[end..].trim_start();
+        }
+
+        let mut position = Position {
+            id: String::new(),
+            board: replay.board,
+            turn: replay.turn,
+            replay: replay,
+            best: vec![],
+            avoid: vec![],
+            comment: None,
+        };
+        for (opcode, operands) in operations(rest) {
+            match opcode.as_str() {
+                "bm" => position.best = operands,
+                "am" => position.avoid = operands,
+                "id" => position.id = operands.join(" "),
+                "c0" => position.comment = Some(operands.join(" ")),
+                _ => (),
+            }
+        }
+        if position.best.is_empty() && position.avoid.is_empty() {
+            return Err("no bm or am operation".to_string());
+        }
+        Ok(position)
+    }
+
+    fn is_move(&self, san: &str, cmove: &Move) -> bool {
+        let mut replay = self.replay;
+        // the board never promotes, so only the squares are compared
+        replay.play_san(san)
+            .map(|played| played & 0xfff == book::encode_move(&cmove.0, &cmove.1, None))
+            .unwrap_or(false)
+    }
+
+    pub fn solves(&self, cmove: &Move) -> bool {
+        (self.best.is_empty() || self.best.iter().any(|san| self.is_move(san, cmove))) &&
+        !self.avoid.iter().any(|san| self.is_move(san, cmove))
+    }
+
+    // Partial credit per move, empty unless the comment lists it
+    fn credits(&self) -> Vec<(String, u32)> {
+        let comment = match self.comment {
+            Some(ref comment) => comment,
+            None => return vec![],
+        };
+        let credits = comment.split(',')
+            .filter_map(|credit| {
+                let mut parts = credit.split('=');
+                match (parts.next(), parts.next().and_then(|n| n.trim().parse().ok())) {
+                    (Some(san), Some(points)) => Some((san.trim().to_string(), points)),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<(String, u32)>>();
+        if credits.len() == comment.split(',').count() {
+            credits
+        } else {
+            vec![]
+        }
+    }
+
+    // Points for playing `cmove` out of those available: the STS credits when
+    // present, otherwise one for a solution
+    pub fn score(&self, cmove: Option<&Move>) -> (u32, u32) {
+        let credits = self.credits();
+        if credits.is_empty() {
+            return (cmove.map_or(false, |cmove| self.solves(cmove)) as u32, 1);
+        }
+        let points = cmove.and_then(|cmove| {
+                credits.iter().find(|&&(ref san, _)| self.is_move(san, cmove))
+            })
+            .map_or(0, |&(_, points)| points);
+        (points, credits.iter().map(|&(_, points)| points).max().unwrap_or(0))
+    }
+}
+
+pub fn read_positions(path: &str) -> Result<Vec<Position>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut positions = vec![];
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path, e))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut position = Position::parse(&line)
+            .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+        if position.id.is_empty() {
+            position.id = format!("line {}", n + 1);
+        }
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+pub struct Outcome {
+    pub best: Option<Move>,
+    pub depth: usize,
+    pub solved: bool,
+    // when the search settled on a solution for good
+    pub time_ms: Option<u64>,
+    pub score: (u32, u32),
+}
+
+pub fn solve(smp: &mut LazySmp, position: &Position, limit: Limit) -> Outcome {
+    smp.reset();
+    let lines = match limit {
+        Limit::Depth(depth) => {
+            smp.search(&position.board, position.turn, depth.min(MAX_DEPTH - 1), 1)
+        }
+        Limit::Time(time_ms) => smp.search_for(&position.board, position.turn, 1, time_ms),
+    };
+
+    let best = lines.first().and_then(|line| line.pv.first().cloned());
+    let solved = best.map_or(false, |cmove| position.solves(&cmove));
+    let iterations = smp.iterations();
+    let settled = iterations.iter()
+        .rposition(|line| line.pv.first().map_or(true, |cmove| !position.solves(cmove)))
+        .map_or(0, |failed| failed + 1);
+    Outcome {
+        best: best,
+        depth: lines.first().map_or(0, |line| line.depth),
+        solved: solved,
+        time_ms: if solved {
+            iterations.get(settled).map(|line| line.time_ms)
+        } else {
+            None
+        },
+        score: position.score(best.as_ref()),
+    }
+}
@@ -0,0 +1,449 @@
+use std::{fmt, ops};
+
+use {available_moves, Board, Color, GameStatus, Piece, Square};
+use endgame::{self, Known};
+use params::Params;
+use pawns::PawnTable;
+
+pub const MATE: i32 = 100000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub fn new(mg: i32, eg: i32) -> Score {
+        Score { mg: mg, eg: eg }
+    }
+
+    pub fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl ops::Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl ops::AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        self.mg += other.mg;
+        self.eg += other.eg;
+    }
+}
+
+impl ops::Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl ops::SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        self.mg -= other.mg;
+        self.eg -= other.eg;
+    }
+}
+
+impl ops::Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl ops::Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, n: i32) -> Score {
+        Score::new(self.mg * n, self.eg * n)
+    }
+}
+
+pub const MAX_PHASE: i32 = 24;
+
+pub fn relative_rank(color: Color, j: usize) -> usize {
+    match color {
+        Color::Black => 7 - j,
+        Color::White => j,
+    }
+}
+
+fn phase_weight(piece: Piece) -> i32 {
+    match piece {
+        Piece::Bishop | Piece::Knight => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+        Piece::Empty | Piece::King | Piece::Pawn => 0,
+    }
+}
+
+// 24 with all minor and major pieces on the board, 0 with only kings and pawns
+pub fn phase(board: &Board) -> i32 {
+    let phase = board.squares
+        .iter()
+        .flatten()
+        .fold(0, |acc, &(_, p)| acc + phase_weight(p));
+    phase.min(MAX_PHASE)
+}
+
+fn pst(params: &Params, color: Color, piece: Piece, i: usize, j: usize) -> Score {
+    let index = match color {
+        Color::Black => j * 8 + i,
+        Color::White => (7 - j) * 8 + i,
+    };
+    params.pst(piece, index)
+}
+
+fn king_zone(color: Color, king: &Square) -> Vec<Square> {
+    let forward = match color {
+        Color::Black => -1,
+        Color::White => 1,
+    };
+    let mut zone = vec![*king];
+    for i in -1..2 {
+        for j in -1..2 {
+            if let Some(square) = king.neighboor(i, j) {
+                if square != *king {
+                    zone.push(square);
+                }
+            }
+        }
+        if let Some(square) = king.neighboor(i, 2 * forward) {
+            zone.push(square);
+        }
+    }
+    zone
+}
+
+// Only has a midgame component, so tapering removes it as material comes off
+fn king_safety(params: &Params, board: &Board, color: Color) -> Score {
+    let king = match board.king(color) {
+        Some(square) => square,
+        None => return Score::default(),
+    };
+    let (ki, kj) = king.indexes();
+    let enemy = color.other();
+    let king_rank = relative_rank(color, kj);
+    let mut mg = 0;
+
+    for i in ki.saturating_sub(1)..(ki + 2).min(8) {
+        let in_front = |c: Color| {
+            (0..8)
+                .filter(|&j| board.squares[i][j] == (c, Piece::Pawn))
+                .map(|j| relative_rank(color, j))
+                .filter(|&rank| rank > king_rank)
+                .map(|rank| rank - king_rank)
+                .min()
+        };
+        let has_pawn = |c: Color| (0..8).any(|j| board.squares[i][j] == (c, Piece::Pawn));
+
+        mg += match in_front(color) {
+            Some(distance) => params.shield[distance],
+            None => params.shield_missing,
+        };
+        if let Some(distance) = in_front(enemy) {
+            mg -= params.storm[distance];
+        }
+
+        if !has_pawn(color) {
+            mg += if has_pawn(enemy) {
+                params.semi_open_king_file
+            } else {
+                params.open_king_file
+            };
+        }
+    }
+
+    let zone = king_zone(color, &king);
+    let mut attackers = 0;
+    let mut weight = 0;
+    for (i, col) in board.squares.iter().enumerate() {
+        for (j, &(c, piece)) in col.iter().enumerate() {
+            if c != enemy || params.attacker_weight[piece as usize] == 0 {
+                continue;
+            }
+            let hits = board.attacks(&Square::from_indexes(i, j))
+                .iter()
+                .filter(|s| zone.contains(s))
+                .count();
+            if hits > 0 {
+                attackers += 1;
+                weight += params.attacker_weight[piece as usize];
+            }
+        }
+    }
+    mg -= weight * params.attack_scale[attackers.min(7)] / 100;
+
+    Score::new(mg, 0)
+}
+
+// The number of moves below which mobility counts against a piece
+fn neutral_mobility(piece: Piece) -> i32 {
+    match piece {
+        Piece::Bishop => 6,
+        Piece::Knight => 4,
+        Piece::Queen => 13,
+        Piece::Rook => 7,
+        Piece::Empty | Piece::King | Piece::Pawn => 0,
+    }
+}
+
+fn mobility(params: &Params, board: &Board, color: Color) -> Score {
+    let mut pawn_attacks = vec![];
+    for (i, col) in board.squares.iter().enumerate() {
+        for (j, &piece) in col.iter().enumerate() {
+            if piece == (color.other(), Piece::Pawn) {
+                pawn_attacks.append(&mut board.attacks(&Square::from_indexes(i, j)));
+            }
+        }
+    }
+
+    let mut score = Score::default();
+    for (i, col) in board.squares.iter().enumerate() {
+        for (j, &(c, piece)) in col.iter().enumerate() {
+            let neutral = neutral_mobility(piece);
+            if c != color || neutral == 0 {
+                continue;
+            }
+            let square = Square::from_indexes(i, j);
+            let count = available_moves(&square, &(c, piece))
+                .into_iter()
+                .filter(|to| board.is_legal(&square, to) && !pawn_attacks.contains(to))
+                .count();
+            score += params.mobility[piece as usize] * (count as i32 - neutral);
+        }
+    }
+    score
+}
+
+fn pieces(params: &Params, board: &Board, color: Color) -> Score {
+    let pawn_at = |c: Color, i: isize, j: isize| {
+        0 <= i && i <= 7 && 0 <= j && j <= 7 &&
+        board.squares[i as usize][j as usize] == (c, Piece::Pawn)
+    };
+    let dir = match color {
+        Color::Black => -1,
+        Color::White => 1,
+    };
+
+    let mut score = Score::default();
+    let mut bishops = 0;
+    for (i, col) in board.squares.iter().enumerate() {
+        for (j, &(c, piece)) in col.iter().enumerate() {
+            if c != color {
+                continue;
+            }
+            let (i, j) = (i as isize, j as isize);
+
+            match piece {
+                Piece::Rook => {
+                    if !(0..8).any(|r| pawn_at(color, i, r)) {
+                        score += if (0..8).any(|r| pawn_at(color.other(), i, r)) {
+                            params.rook_semi_open_file
+                        } else {
+                            params.rook_open_file
+                        };
+                    }
+                    if relative_rank(color, j as usize) == 6 {
+                        score += params.rook_seventh;
+                    }
+                }
+                Piece::Knight => {
+                    // on the enemy half, defended by a pawn and out of reach of enemy pawns
+                    let rank = relative_rank(color, j as usize);
+                    let defended = pawn_at(color, i - 1, j - dir) || pawn_at(color, i + 1, j - dir);
+                    let attackable = (0..8).any(|r| {
+                        (r - j) * dir > 0 &&
+                        (pawn_at(color.other(), i - 1, r) || pawn_at(color.other(), i + 1, r))
+                    });
+                    if 3 <= rank && rank <= 5 && defended && !attackable {
+                        score += params.knight_outpost;
+                    }
+                }
+                Piece::Bishop => {
+                    bishops += 1;
+                    let own_pawns = (0..8)
+                        .flat_map(|f| (0..8).map(move |r| (f, r)))
+                        .filter(|&(f, r)| (f + r) % 2 == (i + j) % 2 && pawn_at(color, f, r))
+                        .count();
+                    score += params.bad_bishop_pawn * own_pawns as i32;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if bishops >= 2 {
+        score += params.bishop_pair;
+    }
+    score
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Term {
+    Material,
+    Pst,
+    Pawns,
+    KingSafety,
+    Mobility,
+    Pieces,
+}
+
+impl Term {
+    fn name(&self) -> &'static str {
+        match *self {
+            Term::Material => "Material",
+            Term::Pst => "PST",
+            Term::Pawns => "Pawns",
+            Term::KingSafety => "King safety",
+            Term::Mobility => "Mobility",
+            Term::Pieces => "Pieces",
+        }
+    }
+}
+
+pub const TERMS: &'static [Term] = &[Term::Material,
+                                     Term::Pst,
+                                     Term::Pawns,
+                                     Term::KingSafety,
+                                     Term::Mobility,
+                                     Term::Pieces];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Breakdown {
+    terms: [[Score; 2]; 6],
+    pub phase: i32,
+}
+
+impl Breakdown {
+    fn add(&mut self, term: Term, color: Color, score: Score) {
+        self.terms[term as usize][color as usize] += score;
+    }
+
+    pub fn get(&self, term: Term, color: Color) -> Score {
+        self.terms[term as usize][color as usize]
+    }
+
+    // From White's point of view
+    pub fn total(&self) -> Score {
+        TERMS.iter().fold(Score::default(), |acc, &term| {
+            acc + self.get(term, Color::White) - self.get(term, Color::Black)
+        })
+    }
+}
+
+fn cell(score: Score) -> String {
+    format!(" {:5.2} {:5.2}",
+            score.mg as f64 / 100.0,
+            score.eg as f64 / 100.0)
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = " ------------+-------------+-------------+-------------\n";
+        writeln!(f, "        Term |    White    |    Black    |    Total")?;
+        writeln!(f, "             |    MG    EG |    MG    EG |    MG    EG")?;
+        write!(f, "{}", separator)?;
+
+        let mut white = Score::default();
+        let mut black = Score::default();
+        for &term in TERMS {
+            let (w, b) = (self.get(term, Color::White), self.get(term, Color::Black));
+            white += w;
+            black += b;
+            writeln!(f,
+                     "{:>12} |{} |{} |{}",
+                     term.name(),
+                     cell(w),
+                     cell(b),
+                     cell(w - b))?;
+        }
+
+        write!(f, "{}", separator)?;
+        writeln!(f,
+                 "{:>12} |{} |{} |{}",
+                 "Total",
+                 cell(white),
+                 cell(black),
+                 cell(white - black))?;
+        write!(f, "\nphase: {}/{}\n", self.phase, MAX_PHASE)?;
+        writeln!(f,
+                 "tapered total: {:+.2} (White side)",
+                 self.total().taper(self.phase) as f64 / 100.0)?;
+        Ok(())
+    }
+}
+
+pub struct Evaluator {
+    params: Params,
+    pawns: PawnTable,
+}
+
+impl Evaluator {
+    pub fn with_params(params: Params) -> Evaluator {
+        Evaluator {
+            params: params,
+            pawns: PawnTable::new(),
+        }
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn breakdown(&mut self, board: &Board) -> Breakdown {
+        let params = &self.params;
+        let mut breakdown = Breakdown { phase: phase(board), ..Breakdown::default() };
+
+        for (i, col) in board.squares.iter().enumerate() {
+            for (j, &(color, piece)) in col.iter().enumerate() {
+                if piece != Piece::Empty {
+                    breakdown.add(Term::Material, color, params.material(piece));
+                    breakdown.add(Term::Pst, color, pst(params, color, piece, i, j));
+                }
+            }
+        }
+
+        let pawns = self.pawns.probe(params, board);
+        for &color in &[Color::Black, Color::White] {
+            breakdown.add(Term::Pawns, color, pawns.scores[color as usize]);
+            breakdown.add(Term::Pawns, color, pawns.passed_score(params, board, color));
+            breakdown.add(Term::KingSafety, color, king_safety(params, board, color));
+            breakdown.add(Term::Mobility, color, mobility(params, board, color));
+            breakdown.add(Term::Pieces, color, pieces(params, board, color));
+        }
+
+        breakdown
+    }
+
+    // Centipawns from White's point of view, `turn` to move
+    pub fn evaluate(&mut self, board: &Board, turn: Color) -> i32 {
+        if let GameStatus::Finished(color) = board.status() {
+            return match color {
+                Color::Black => -MATE,
+                Color::White => MATE,
+            };
+        }
+
+        let breakdown = self.breakdown(board);
+        let score = breakdown.total().taper(breakdown.phase);
+        match endgame::probe(board, turn, &self.params) {
+            Some(Known::Exact(score)) => score,
+            Some(Known::Scale(scale)) => {
+                let favoured = if score > 0 { Color::White } else { Color::Black };
+                score * scale[favoured as usize] / endgame::SCALE_NORMAL
+            }
+            None => score,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::Write;
+
+use {move_string, Color, GameTreeNode, Move};
+use eval::Evaluator;
+
+// What is shown of a node: the move leading to it with its average score for the
+// side that played it, the size of the sampled tree below it and its position
+struct Summary {
+    cmove: Option<Move>,
+    score: f64,
+    size: usize,
+    fen: String,
+}
+
+impl GameTreeNode {
+    fn summary(&self, cmove: Option<Move>, color: Color, evaluator: &mut Evaluator) -> Summary {
+        Summary {
+            cmove: cmove,
+            score: self.avg_score(color, evaluator),
+            size: self.size(),
+            fen: self.board.to_fen(self.turn),
+        }
+    }
+
+    fn explored(&self) -> Vec<(Move, &GameTreeNode)> {
+        self.children
+            .iter()
+            .filter_map(|(&cmove, node)| node.as_ref().map(|node| (cmove, node)))
+            .collect()
+    }
+
+    // Graphviz digraph of the top `levels` levels below this node
+    pub fn to_dot(&self, levels: usize, evaluator: &mut Evaluator) -> String {
+        let mut out = String::from("digraph tree {\n");
+        out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        let mut count = 0;
+        self.write_dot(&mut out, &mut count, None, self.turn, levels, evaluator);
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot(&self,
+                 out: &mut String,
+                 count: &mut usize,
+                 cmove: Option<Move>,
+                 color: Color,
+                 levels: usize,
+                 evaluator: &mut Evaluator)
+                 -> usize {
+        let id = *count;
+        *count += 1;
+
+        let summary = self.summary(cmove, color, evaluator);
+        out.push_str(&format!("    n{} [label=\"{}\\nscore {:.1}\\nsize {}\\n{}\"];\n",
+                              id,
+                              summary.cmove.as_ref().map_or("root".to_string(), move_string),
+                              summary.score,
+                              summary.size,
+                              summary.fen));
+
+        if levels > 0 {
+            for (child_move, child) in self.explored() {
+                let child_id =
+                    child.write_dot(out, count, Some(child_move), self.turn, levels - 1, evaluator);
+                out.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n",
+                                      id,
+                                      child_id,
+                                      move_string(&child_move)));
+            }
+        }
+        id
+    }
+
+    // Nested JSON objects of the top `levels` levels below this node
+    pub fn to_json(&self, levels: usize, evaluator: &mut Evaluator) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out, None, self.turn, levels, evaluator);
+        out.push('\n');
+        out
+    }
+
+    fn write_json(&self,
+                  out: &mut String,
+                  cmove: Option<Move>,
+                  color: Color,
+                  levels: usize,
+                  evaluator: &mut Evaluator) {
+        let summary = self.summary(cmove, color, evaluator);
+        let cmove = summary.cmove.as_ref().map_or("null".to_string(), |m| {
+            format!("\"{}\"", move_string(m))
+        });
+        out.push_str(&format!("{{\"move\":{},\"score\":{:.1},\"size\":{},\"fen\":\"{}\",\
+                               \"children\":[",
+                              cmove,
+                              summary.score,
+                              summary.size,
+                              summary.fen));
+
+        if levels > 0 {
+            for (index, (child_move, child)) in self.explored().into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                child.write_json(out, Some(child_move), self.turn, levels - 1, evaluator);
+            }
+        }
+        out.push_str("]}");
+    }
+}
+
+// The format follows the extension: `.json` for JSON, DOT otherwise
+pub fn write_tree(tree: &GameTreeNode,
+                  path: &str,
+                  levels: usize,
+                  evaluator: &mut Evaluator)
+                  -> Result<(), String> {
+    let contents = if path.ends_with(".json") {
+        tree.to_json(levels, evaluator)
+    } else {
+        tree.to_dot(levels, evaluator)
+    };
+
+    let mut file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    file.write_all(contents.as_bytes()).map_err(|e| format!("{}: {}", path, e))
+}
@@ -0,0 +1,105 @@
+use {Board, Color, Piece, EMPTY};
+
+impl Board {
+    pub fn from_fen(fen: &str) -> Result<(Board, Color), String> {
+        let fields = fen.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() {
+            return Err("empty FEN".to_string());
+        }
+
+        let ranks = fields[0].split('/').collect::<Vec<&str>>();
+        if ranks.len() != 8 {
+            return Err(format!("expected 8 ranks in FEN, found {}", ranks.len()));
+        }
+
+        let mut board = Board { squares: [[EMPTY; 8]; 8] };
+        for (r, rank) in ranks.iter().enumerate() {
+            let j = 7 - r;
+            let mut i = 0;
+            for c in rank.chars() {
+                if let Some(n) = c.to_digit(10) {
+                    i += n as usize;
+                    continue;
+                }
+                if i > 7 {
+                    return Err(format!("too many files on rank {} of FEN", 8 - r));
+                }
+
+                let color = if c.is_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let piece = match c {
+                    'B' | 'b' => Piece::Bishop,
+                    'K' | 'k' => Piece::King,
+                    'N' | 'n' => Piece::Knight,
+                    'P' | 'p' => Piece::Pawn,
+                    'Q' | 'q' => Piece::Queen,
+                    'R' | 'r' => Piece::Rook,
+                    _ => return Err(format!("invalid piece '{}' in FEN", c)),
+                };
+                board.squares[i][j] = (color, piece);
+                i += 1;
+            }
+            if i != 8 {
+                return Err(format!("expected 8 files on rank {} of FEN, found {}", 8 - r, i));
+            }
+        }
+
+        if board.king(Color::White).is_none() || board.king(Color::Black).is_none() {
+            return Err("FEN must contain both kings".to_string());
+        }
+
+        let turn = match fields.get(1) {
+            None | Some(&"w") => Color::White,
+            Some(&"b") => Color::Black,
+            Some(other) => return Err(format!("invalid side to move '{}' in FEN", other)),
+        };
+
+        Ok((board, turn))
+    }
+
+    // Castling rights and en passant are not tracked by `Board`, so they are
+    // always written as `-`
+    pub fn to_fen(&self, turn: Color) -> String {
+        let mut ranks = vec![];
+        for j in (0..8).rev() {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for i in 0..8 {
+                let (color, piece) = self.squares[i][j];
+                let c = match piece {
+                    Piece::Empty => {
+                        empty += 1;
+                        continue;
+                    }
+                    Piece::Bishop => 'b',
+                    Piece::King => 'k',
+                    Piece::Knight => 'n',
+                    Piece::Pawn => 'p',
+                    Piece::Queen => 'q',
+                    Piece::Rook => 'r',
+                };
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                match color {
+                    Color::White => rank.extend(c.to_uppercase()),
+                    Color::Black => rank.push(c),
+                }
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            ranks.push(rank);
+        }
+
+        let side = match turn {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        format!("{} {} - - 0 1", ranks.join("/"), side)
+    }
+}
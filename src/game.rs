@@ -0,0 +1,607 @@
+use std::fmt;
+
+use {Board, Color, Move, Piece};
+use eval::MATE;
+use pgn::{self, Replay};
+use search::MAX_PLY;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(color: Color) -> GameResult {
+        match color {
+            Color::Black => GameResult::BlackWins,
+            Color::White => GameResult::WhiteWins,
+        }
+    }
+
+    // White's points
+    pub fn score(&self) -> f64 {
+        match *self {
+            GameResult::WhiteWins => 1.0,
+            GameResult::BlackWins => 0.0,
+            GameResult::Draw => 0.5,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = match *self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        };
+        write!(f, "{}", result)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    Adjudicated,
+    KnownEndgame,
+    IllegalMove,
+    NoMove,
+    Time,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match *self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "threefold repetition",
+            Termination::FiftyMoves => "fifty move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::Adjudicated => "adjudicated on score",
+            Termination::KnownEndgame => "adjudicated as a known endgame",
+            Termination::IllegalMove => "illegal move",
+            Termination::NoMove => "no move",
+            Termination::Time => "lost on time",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+fn insufficient_material(board: &Board) -> bool {
+    let pieces = board.squares
+        .iter()
+        .flatten()
+        .filter(|&&(_, piece)| piece != Piece::Empty && piece != Piece::King)
+        .map(|&(_, piece)| piece)
+        .collect::<Vec<Piece>>();
+    match pieces.len() {
+        0 => true,
+        1 => pieces[0] == Piece::Bishop || pieces[0] == Piece::Knight,
+        _ => false,
+    }
+}
+
+// `[%eval]` values are pawns or `#` and a number of moves to mate, from
+// White's side; the result is centipawns for `mover`
+fn parse_eval(value: &str, mover: Color) -> Option<i32> {
+    let eval = if let Some(moves) = value.strip_prefix('#') {
+        let moves = moves.parse::<i32>().ok()?;
+        if moves < 0 { -MATE - 2 * moves } else { MATE - 2 * moves }
+    } else {
+        (value.parse::<f64>().ok()? * 100.0).round() as i32
+    };
+    match mover {
+        Color::White => Some(eval),
+        Color::Black => Some(-eval),
+    }
+}
+
+// `[%clk]` as hours, minutes and seconds
+fn parse_clock(value: &str) -> Option<u64> {
+    value.split(':').try_fold(0, |ms, field| {
+        field.parse::<f64>().ok().map(|value| ms * 60 + (value * 1000.0) as u64)
+    })
+}
+
+// A move as it was played, with what is known about it
+#[derive(Clone, Debug)]
+pub struct MoveRecord {
+    pub cmove: Move,
+    pub promotion: Option<Piece>,
+    pub san: String,
+    // the mover's time left after the move
+    pub clock_ms: Option<u64>,
+    // the mover's score in centipawns from the search behind the move
+    pub eval: Option<i32>,
+    pub comment: Option<String>,
+    // the position after the move
+    position: Replay,
+    // plies since the last capture or pawn move, this one included
+    quiet: usize,
+}
+
+// A game from its starting position: the moves played so far, the result once
+// it is decided and the PGN tags. Moves can be taken back and played again, and
+// playing a different move from an earlier position drops those after it.
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    start: Replay,
+    moves: Vec<MoveRecord>,
+    // the moves behind the current position, any others can be redone
+    ply: usize,
+    pub result: Option<GameResult>,
+    pub termination: Option<Termination>,
+}
+
+impl Game {
+    pub fn from_replay(start: Replay) -> Game {
+        Game {
+            tags: vec![],
+            start: start,
+            moves: vec![],
+            ply: 0,
+            result: None,
+            termination: None,
+        }
+    }
+
+    // A parsed PGN game, from its FEN tag if it has one
+    pub fn from_pgn(pgn: &pgn::Game) -> Result<Game, String> {
+        let mut game = Game::from_replay(pgn.start()?);
+        game.tags = pgn.tags
+            .iter()
+            .filter(|&&(ref name, _)| {
+                name != "FEN" && name != "SetUp" && name != "Result" && name != "Variant"
+            })
+            .cloned()
+            .collect();
+        for (san, comment) in pgn.moves.iter().zip(&pgn.comments) {
+            let mover = game.turn();
+            let (cmove, promotion) = game.position().parse_san(san)?;
+            game.play(cmove, promotion)?.read_annotations(comment, mover);
+        }
+        game.result = pgn.result.map(|score| match score {
+            s if s > 0.75 => GameResult::WhiteWins,
+            s if s < 0.25 => GameResult::BlackWins,
+            _ => GameResult::Draw,
+        });
+        Ok(game)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|&&(ref tag, _)| tag == name).map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        self.tags.retain(|&(ref tag, _)| tag != name);
+        self.tags.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn start(&self) -> &Replay {
+        &self.start
+    }
+
+    pub fn position(&self) -> &Replay {
+        match self.ply {
+            0 => &self.start,
+            ply => &self.moves[ply - 1].position,
+        }
+    }
+
+    pub fn board(&self) -> Board {
+        self.position().board
+    }
+
+    pub fn turn(&self) -> Color {
+        self.position().turn
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.position().legal_moves()
+    }
+
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    // All moves recorded, including those taken back
+    pub fn moves(&self) -> &[MoveRecord] {
+        &self.moves
+    }
+
+    // The moves up to the current position in UCI's notation
+    pub fn uci_moves(&self) -> Vec<String> {
+        let mut before = &self.start;
+        let mut moves = vec![];
+        for record in &self.moves[..self.ply] {
+            moves.push(before.uci(&record.cmove, record.promotion));
+            before = &record.position;
+        }
+        moves
+    }
+
+    // The move that led to the current position
+    pub fn last_mut(&mut self) -> Option<&mut MoveRecord> {
+        match self.ply {
+            0 => None,
+            ply => Some(&mut self.moves[ply - 1]),
+        }
+    }
+
+    // Plays a legal move from the current position, replacing the moves that
+    // could have been redone and any result
+    pub fn play(&mut self,
+                cmove: Move,
+                promotion: Option<Piece>)
+                -> Result<&mut MoveRecord, String> {
+        let before = *self.position();
+        let cmove = before.canonical(cmove);
+        if !before.legal_moves().contains(&cmove) {
+            return Err(format!("illegal move {}{}", cmove.0, cmove.1));
+        }
+        let quiet = match self.ply {
+            0 => 0,
+            ply => self.moves[ply - 1].quiet,
+        };
+        let reset = before.board.get(&cmove.0).1 == Piece::Pawn ||
+                    before.board.get(&cmove.1).1 != Piece::Empty;
+
+        let promotion = before.promotion(&cmove, promotion);
+        let mut position = before;
+        position.play(cmove, promotion)?;
+        self.moves.truncate(self.ply);
+        self.moves.push(MoveRecord {
+            cmove: cmove,
+            promotion: promotion,
+            san: before.san(&cmove, promotion),
+            clock_ms: None,
+            eval: None,
+            comment: None,
+            position: position,
+            quiet: if reset { 0 } else { quiet + 1 },
+        });
+        self.ply += 1;
+        self.result = None;
+        self.termination = None;
+        Ok(&mut self.moves[self.ply - 1])
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.ply > 0 && self.go_to(self.ply - 1)
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.go_to(self.ply + 1)
+    }
+
+    // Moves to the position after `ply` moves, false if there are not as many
+    pub fn go_to(&mut self, ply: usize) -> bool {
+        if ply > self.moves.len() {
+            return false;
+        }
+        self.ply = ply;
+        true
+    }
+
+    pub fn finish(&mut self, result: GameResult, termination: Termination) {
+        self.result = Some(result);
+        self.termination = Some(termination);
+    }
+
+    // Whether the rules end the game in the current position, and how
+    pub fn outcome(&self) -> Option<(GameResult, Termination)> {
+        let position = self.position();
+        if position.legal_moves().is_empty() {
+            return Some(if position.board.in_check(position.turn) {
+                (GameResult::win_for(position.turn.other()), Termination::Checkmate)
+            } else {
+                (GameResult::Draw, Termination::Stalemate)
+            });
+        }
+        if insufficient_material(&position.board) {
+            return Some((GameResult::Draw, Termination::InsufficientMaterial));
+        }
+        if self.ply > 0 && self.moves[self.ply - 1].quiet >= 100 {
+            return Some((GameResult::Draw, Termination::FiftyMoves));
+        }
+        let key = position.key();
+        let repetitions = Some(&self.start)
+            .into_iter()
+            .chain(self.moves[..self.ply].iter().map(|record| &record.position))
+            .filter(|other| other.key() == key)
+            .count();
+        if repetitions >= 3 {
+            return Some((GameResult::Draw, Termination::Repetition));
+        }
+        None
+    }
+
+    // The game in PGN up to the current position, with clock and evaluation
+    // annotations in the comments
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        let result = self.result.map_or("*".to_string(), |result| result.to_string());
+        let roster = [("Event", "?"),
+                      ("Site", "?"),
+                      ("Date", "????.??.??"),
+                      ("Round", "?"),
+                      ("White", "?"),
+                      ("Black", "?")];
+        for &(name, default) in &roster {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, self.tag(name).unwrap_or(default)));
+        }
+        pgn.push_str(&format!("[Result \"{}\"]\n", result));
+        if self.start.chess960 {
+            pgn.push_str("[Variant \"Chess960\"]\n");
+        }
+        let standard = Replay::new().key();
+        if self.start.chess960 || self.start.key() != standard {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{}\"]\n", self.start.to_fen(false)));
+        }
+        if let Some(termination) = self.termination {
+            pgn.push_str(&format!("[Termination \"{}\"]\n", termination));
+        }
+        for &(ref name, ref value) in &self.tags {
+            let termination = name == "Termination" && self.termination.is_some();
+            if !termination && !roster.iter().any(|&(tag, _)| tag == name) {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        let mut turn = self.start.turn;
+        for (ply, record) in self.moves[..self.ply].iter().enumerate() {
+            let number = (ply + (turn == Color::Black) as usize) / 2 + 1;
+            if ply == 0 && turn == Color::Black {
+                tokens.push(format!("{}...", number));
+            } else if turn == Color::White {
+                tokens.push(format!("{}.", number));
+            }
+            tokens.push(record.san.clone());
+            let comment = record.annotations(turn);
+            if !comment.is_empty() {
+                tokens.push(format!("{{{}}}", comment));
+            }
+            turn = turn.other();
+        }
+        tokens.push(result);
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+impl MoveRecord {
+    // The inverse of `annotations`: `[%eval]` and `[%clk]` are taken out of a
+    // PGN comment and whatever is left is kept as the comment
+    fn read_annotations(&mut self, text: &str, mover: Color) {
+        let mut rest = text.to_string();
+        while let Some(start) = rest.find("[%") {
+            let end = match rest[start..].find(']') {
+                Some(end) => start + end,
+                None => break,
+            };
+            {
+                let mut fields = rest[start + 2..end].split_whitespace();
+                match (fields.next(), fields.next()) {
+                    (Some("eval"), Some(value)) => self.eval = parse_eval(value, mover),
+                    (Some("clk"), Some(value)) => self.clock_ms = parse_clock(value),
+                    _ => (),
+                }
+            }
+            rest = format!("{} {}", &rest[..start], &rest[end + 1..]);
+        }
+        let rest = rest.split_whitespace().collect::<Vec<&str>>().join(" ");
+        self.comment = if rest.is_empty() { None } else { Some(rest) };
+    }
+
+    // `[%eval]` from White's side in pawns or as a mate distance, `[%clk]`
+    // and the comment
+    fn annotations(&self, mover: Color) -> String {
+        let mut annotations = vec![];
+        if let Some(eval) = self.eval {
+            let eval = match mover {
+                Color::White => eval,
+                Color::Black => -eval,
+            };
+            if eval.abs() > MATE - MAX_PLY as i32 {
+                let plies = MATE - eval.abs() - 1;
+                let sign = if eval > 0 { "" } else { "-" };
+                annotations.push(format!("[%eval #{}{}]", sign, (plies + 1) / 2));
+            } else {
+                annotations.push(format!("[%eval {:.2}]", eval as f64 / 100.0));
+            }
+        }
+        if let Some(ms) = self.clock_ms {
+            let seconds = ms / 1000;
+            annotations.push(format!("[%clk {}:{:02}:{:02}]",
+                                     seconds / 3600,
+                                     seconds / 60 % 60,
+                                     seconds % 60));
+        }
+        if let Some(ref comment) = self.comment {
+            annotations.push(comment.replace('}', ")"));
+        }
+        annotations.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Color, Piece, Square};
+    use eval::MATE;
+    use pgn::{self, Replay};
+    use super::{Game, GameResult, Termination};
+
+    fn from_fen(fen: &str) -> Game {
+        Game::from_replay(Replay::from_fen(fen).unwrap())
+    }
+
+    fn square(name: &str) -> Square {
+        let name = name.as_bytes();
+        Square::new(name[0] as char, name[1] - b'0')
+    }
+
+    fn play_san(game: &mut Game, moves: &[&str]) {
+        for san in moves {
+            let (cmove, promotion) = game.position().parse_san(san).unwrap();
+            game.play(cmove, promotion).unwrap();
+        }
+    }
+
+    fn sans(game: &Game) -> Vec<String> {
+        game.moves().iter().map(|record| record.san.clone()).collect()
+    }
+
+    #[test]
+    fn san() {
+        let mut game = Game::from_replay(Replay::new());
+        play_san(&mut game, &["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"]);
+        assert_eq!(sans(&game),
+                   vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"]);
+        assert_eq!(game.uci_moves().last().unwrap(), "e1g1");
+
+        // by file, by rank, and both when neither is enough
+        let game = from_fen("5k2/8/8/8/Q6Q/8/8/Q2QK2N w - - 0 1");
+        let position = game.position();
+        assert_eq!(position.san(&(square("h1"), square("f2")), None), "Nf2");
+        assert_eq!(position.san(&(square("h4"), square("e4")), None), "Qhe4");
+        assert_eq!(position.san(&(square("a1"), square("a2")), None), "Q1a2");
+        assert_eq!(position.san(&(square("a4"), square("c4")), None), "Qac4");
+        assert_eq!(position.san(&(square("a1"), square("d4")), None), "Qa1d4");
+        assert_eq!(position.parse_san("Qa1d4").unwrap().0, (square("a1"), square("d4")));
+        assert!(position.parse_san("Qad4").is_err());
+    }
+
+    #[test]
+    fn checks_and_mate() {
+        let mut game = Game::from_replay(Replay::new());
+        play_san(&mut game, &["f3", "e5", "g4", "Qh4#"]);
+        assert_eq!(sans(&game)[3], "Qh4#");
+        assert_eq!(game.outcome(), Some((GameResult::BlackWins, Termination::Checkmate)));
+    }
+
+    #[test]
+    fn en_passant() {
+        let mut game = from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        play_san(&mut game, &["exd6"]);
+        assert_eq!(sans(&game), vec!["exd6"]);
+        assert!(game.board().get(&square("d5")).1 == Piece::Empty);
+        assert!(game.board().get(&square("d6")) == (Color::White, Piece::Pawn));
+    }
+
+    #[test]
+    fn promotion() {
+        let mut game = from_fen("k7/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        play_san(&mut game, &["e8=N"]);
+        assert_eq!(sans(&game), vec!["e8=N"]);
+        assert!(game.board().get(&square("e8")) == (Color::White, Piece::Knight));
+        assert_eq!(game.uci_moves(), vec!["e7e8n"]);
+
+        game.undo();
+        play_san(&mut game, &["e8=Q+"]);
+        assert_eq!(sans(&game), vec!["e8=Q+"]);
+
+        // engines give no piece and get a queen, which the UCI move must name
+        game.undo();
+        game.play((square("e7"), square("e8")), None).unwrap();
+        assert_eq!(game.moves()[0].promotion, Some(Piece::Queen));
+        assert_eq!(sans(&game), vec!["e8=Q+"]);
+        assert_eq!(game.uci_moves(), vec!["e7e8q"]);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut game = Game::from_replay(Replay::new());
+        play_san(&mut game, &["e4", "e5", "Nf3"]);
+        assert!(game.undo());
+        assert!(game.undo());
+        assert_eq!(game.ply(), 1);
+        assert_eq!(game.turn(), Color::Black);
+        assert!(game.redo());
+        assert_eq!(game.ply(), 2);
+        assert!(game.go_to(0));
+        assert!(!game.undo());
+        assert!(game.go_to(3));
+        assert!(!game.redo());
+
+        // a different move drops the ones that could have been redone
+        game.go_to(1);
+        play_san(&mut game, &["c5"]);
+        assert_eq!(sans(&game), vec!["e4", "c5"]);
+        assert!(!game.redo());
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let mut game = from_fen("4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        game.set_tag("Event", "test");
+        play_san(&mut game, &["exd6"]);
+        {
+            let record = game.last_mut().unwrap();
+            record.eval = Some(250);
+            record.clock_ms = Some(3723000);
+            record.comment = Some("takes en passant".to_string());
+        }
+        play_san(&mut game, &["Kd7"]);
+        game.last_mut().unwrap().eval = Some(-MATE + 4);
+        play_san(&mut game, &["b8=Q"]);
+        game.last_mut().unwrap().clock_ms = Some(59000);
+        game.finish(GameResult::WhiteWins, Termination::Adjudicated);
+
+        let text = game.to_pgn();
+        assert!(text.contains("[SetUp \"1\"]"));
+        assert!(text.contains("[FEN \"4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1\"]"));
+        let movetext = text.replace('\n', " ");
+        assert!(movetext.contains("1. exd6 {[%eval 2.50] [%clk 1:02:03] takes en passant} Kd7 \
+                               {[%eval #2]}"));
+        assert!(movetext.contains("2. b8=Q {[%clk 0:00:59]} 1-0"));
+
+        let parsed = pgn::parse_games(&text);
+        assert_eq!(parsed.len(), 1);
+        let imported = Game::from_pgn(&parsed[0]).unwrap();
+        assert_eq!(imported.moves()[0].eval, Some(250));
+        assert_eq!(imported.moves()[0].clock_ms, Some(3723000));
+        assert_eq!(imported.moves()[0].comment, Some("takes en passant".to_string()));
+        assert_eq!(imported.moves()[1].eval, Some(-MATE + 4));
+        assert_eq!(imported.moves()[2].promotion, Some(Piece::Queen));
+        assert_eq!(imported.result, Some(GameResult::WhiteWins));
+        assert_eq!(imported.to_pgn(), text);
+    }
+
+    #[test]
+    fn comments_across_lines_and_variations() {
+        let text = "[Event \"x\"]\n\n{before} 1. e4 {a long\ncomment} (1. d4 {not kept}) \
+                    e5 $1 2. Nf3 {[%clk 0:01:00]} *\n";
+        let parsed = pgn::parse_games(text);
+        assert_eq!(parsed[0].moves, vec!["e4", "e5", "Nf3"]);
+        assert_eq!(parsed[0].comments, vec!["a long comment", "", "[%clk 0:01:00]"]);
+
+        let game = Game::from_pgn(&parsed[0]).unwrap();
+        assert_eq!(game.moves()[0].comment, Some("a long comment".to_string()));
+        assert_eq!(game.moves()[2].clock_ms, Some(60000));
+        assert_eq!(game.moves()[2].comment, None);
+    }
+}
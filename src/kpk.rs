@@ -0,0 +1,198 @@
+use std::sync::OnceLock;
+
+// King and pawn against king, solved by retrograde analysis for normal chess
+// rules. Positions are seen from the side with the pawn, which is White here,
+// with the pawn on files a to d; `probe` mirrors the rest onto these. Squares
+// are numbered rank * 8 + file.
+const SIZE: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+const WHITE: usize = 0;
+const BLACK: usize = 1;
+
+static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+fn index(turn: usize, black_king: usize, white_king: usize, pawn: usize) -> usize {
+    white_king | black_king << 6 | turn << 12 | (pawn % 8) << 13 | (6 - pawn / 8) << 15
+}
+
+fn distance(a: usize, b: usize) -> usize {
+    let files = (a % 8) as isize - (b % 8) as isize;
+    let ranks = (a / 8) as isize - (b / 8) as isize;
+    files.abs().max(ranks.abs()) as usize
+}
+
+fn king_moves(square: usize) -> Vec<usize> {
+    (0..64).filter(|&to| distance(square, to) == 1).collect()
+}
+
+fn pawn_attacks(pawn: usize, square: usize) -> bool {
+    square / 8 == pawn / 8 + 1 && square % 8 != pawn % 8 && distance(pawn, square) == 1
+}
+
+fn initial(turn: usize, black_king: usize, white_king: usize, pawn: usize) -> u8 {
+    if distance(white_king, black_king) <= 1 || white_king == pawn || black_king == pawn ||
+       (turn == WHITE && pawn_attacks(pawn, black_king)) {
+        return INVALID;
+    }
+
+    let queening = pawn + 8;
+    // promotes and the new queen cannot be taken
+    if turn == WHITE && pawn / 8 == 6 && white_king != queening &&
+       (distance(black_king, queening) > 1 || distance(white_king, queening) == 1) {
+        return WIN;
+    }
+
+    if turn == BLACK {
+        let moves = king_moves(black_king);
+        let stalemate = moves.iter()
+            .all(|&to| pawn_attacks(pawn, to) || distance(white_king, to) <= 1);
+        let takes_pawn = moves.contains(&pawn) && distance(white_king, pawn) > 1;
+        if stalemate || takes_pawn {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+// A position is decided once one move reaches a good result for the side to
+// move, or all of them reach a bad one
+fn classify(db: &[u8], turn: usize, black_king: usize, white_king: usize, pawn: usize) -> u8 {
+    let (good, bad) = if turn == WHITE { (WIN, DRAW) } else { (DRAW, WIN) };
+
+    let mut result = 0;
+    if turn == WHITE {
+        for to in king_moves(white_king) {
+            result |= db[index(BLACK, black_king, to, pawn)];
+        }
+        if pawn / 8 < 6 {
+            result |= db[index(BLACK, black_king, white_king, pawn + 8)];
+        }
+        if pawn / 8 == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+            result |= db[index(BLACK, black_king, white_king, pawn + 16)];
+        }
+    } else {
+        for to in king_moves(black_king) {
+            result |= db[index(WHITE, to, white_king, pawn)];
+        }
+    }
+
+    if result & good != 0 {
+        good
+    } else if result & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+fn build() -> Vec<u64> {
+    let decode = |i: usize| {
+        let pawn = (6 - (i >> 15)) * 8 + (i >> 13 & 3);
+        (i >> 12 & 1, i >> 6 & 63, i & 63, pawn)
+    };
+
+    let mut db = (0..SIZE)
+        .map(|i| {
+            let (turn, black_king, white_king, pawn) = decode(i);
+            initial(turn, black_king, white_king, pawn)
+        })
+        .collect::<Vec<u8>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..SIZE {
+            if db[i] == UNKNOWN {
+                let (turn, black_king, white_king, pawn) = decode(i);
+                let result = classify(&db, turn, black_king, white_king, pawn);
+                if result != UNKNOWN {
+                    db[i] = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut bits = vec![0; SIZE / 64];
+    for (i, &result) in db.iter().enumerate() {
+        if result == WIN {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+    bits
+}
+
+// Built on first use and shared by every evaluator
+fn bitbase() -> &'static [u64] {
+    BITBASE.get_or_init(build)
+}
+
+// Whether the side with the pawn wins, squares given as (file, rank) indexes
+// from that side's point of view with the pawn moving up the board. None when
+// the pawn stands on the first or last rank, which the table does not cover.
+pub fn probe(strong_to_move: bool,
+             strong_king: (usize, usize),
+             weak_king: (usize, usize),
+             pawn: (usize, usize))
+             -> Option<bool> {
+    if pawn.1 < 1 || pawn.1 > 6 {
+        return None;
+    }
+
+    // mirror so the pawn is on files a to d
+    let file = |(i, j): (usize, usize)| if pawn.0 > 3 { (7 - i, j) } else { (i, j) };
+    let square = |(i, j): (usize, usize)| j * 8 + i;
+
+    let turn = if strong_to_move { WHITE } else { BLACK };
+    let i = index(turn,
+                  square(file(weak_king)),
+                  square(file(strong_king)),
+                  square(file(pawn)));
+    Some(bitbase()[i / 64] & 1 << (i % 64) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::probe;
+
+    #[test]
+    fn opposition() {
+        // Ke5 and Pe4 against Ke7: only a win with Black to move
+        assert_eq!(probe(true, (4, 4), (4, 6), (4, 3)), Some(false));
+        assert_eq!(probe(false, (4, 4), (4, 6), (4, 3)), Some(true));
+    }
+
+    #[test]
+    fn king_in_front_on_the_sixth() {
+        assert_eq!(probe(true, (4, 5), (4, 7), (4, 4)), Some(true));
+        assert_eq!(probe(false, (4, 5), (4, 7), (4, 4)), Some(true));
+    }
+
+    #[test]
+    fn rook_pawns() {
+        // the defending king reaches the corner
+        assert_eq!(probe(true, (4, 3), (0, 7), (0, 1)), Some(false));
+        assert_eq!(probe(true, (3, 3), (7, 7), (7, 1)), Some(false));
+        // but not when it is too far away
+        assert_eq!(probe(true, (1, 5), (7, 0), (0, 4)), Some(true));
+    }
+
+    #[test]
+    fn mirrored_files() {
+        for file in 0..8 {
+            assert_eq!(probe(false, (file, 4), (file, 6), (file, 3)),
+                       probe(false, (7 - file, 4), (7 - file, 6), (7 - file, 3)));
+        }
+    }
+
+    #[test]
+    fn edge_ranks() {
+        assert_eq!(probe(true, (4, 2), (0, 0), (4, 7)), None);
+        assert_eq!(probe(false, (4, 2), (0, 7), (4, 0)), None);
+    }
+}
@@ -1,20 +1,58 @@
-#![feature(conservative_impl_trait, plugin)]
-
-#![plugin(clippy)]
-
 extern crate futures;
 extern crate futures_cpupool;
 extern crate rand;
 extern crate time;
 
-use futures::{future, Future};
-use futures_cpupool::{CpuPool, CpuFuture};
-use rand::Rng;
-use std::{convert, fmt};
-use std::collections::HashMap;
+mod book;
+mod clock;
+mod engine;
+mod epd;
+mod endgame;
+mod eval;
+mod export;
+mod fen;
+mod game;
+mod kpk;
+mod movepick;
+mod openings;
+mod params;
+mod pawns;
+mod pgn;
+mod search;
+mod see;
+mod smp;
+mod sprt;
+mod tournament;
+mod tt;
+mod tune;
+mod uci;
+mod zobrist;
+
+use book::{Book, Selection};
+use clock::{Clock, TimeControl};
+use engine::{Algorithm, Engine, EngineConfig};
+use epd::Limit;
+use eval::{Evaluator, MATE};
+use game::{Game, GameResult, Termination};
+use openings::OpeningTree;
+use params::Params;
+use pgn::Replay;
+use search::SearchInfo;
+use smp::LazySmp;
+use sprt::Sprt;
+use tournament::{Adjudication, Match, Player, UciEngine};
+use tune::Tuner;
+use futures::Future;
+use futures_cpupool::CpuPool;
+use rand::{Isaac64Rng, Rng, SeedableRng};
+use std::{convert, env, fmt, fs, io, process};
+use std::io::BufRead;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum Color {
+pub enum Color {
     Black,
     White,
 }
@@ -29,7 +67,7 @@ impl Color {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum Piece {
+pub enum Piece {
     Bishop,
     Empty,
     King,
@@ -39,18 +77,6 @@ enum Piece {
     Rook,
 }
 
-impl Piece {
-    fn value(&self) -> usize {
-        match *self {
-            Piece::Empty | Piece::King => 0,
-            Piece::Bishop | Piece::Knight => 3,
-            Piece::Pawn => 1,
-            Piece::Queen => 9,
-            Piece::Rook => 5,
-        }
-    }
-}
-
 type ColorPiece = (Color, Piece);
 
 const EMPTY: ColorPiece = (Color::White, Piece::Empty);
@@ -58,8 +84,8 @@ const EMPTY: ColorPiece = (Color::White, Piece::Empty);
 const FILES: &'static [char] = &['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
 const RANKS: &'static [u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-struct Square {
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Square {
     file: char,
     rank: u8,
 }
@@ -81,6 +107,11 @@ impl Square {
          RANKS.iter().position(|&r| r == self.rank).unwrap())
     }
 
+    fn index(&self) -> usize {
+        let (i, j) = self.indexes();
+        i * 8 + j
+    }
+
     fn in_between(&self, other: &Square) -> Vec<Square> {
         let (si, sj) = self.indexes();
         let (oi, oj) = other.indexes();
@@ -169,7 +200,11 @@ impl convert::From<(char, u8)> for Square {
     }
 }
 
-type Move = (Square, Square);
+pub type Move = (Square, Square);
+
+fn move_string(cmove: &Move) -> String {
+    format!("{}{}", cmove.0, cmove.1)
+}
 
 fn available_moves(square: &Square, piece: &ColorPiece) -> Vec<Square> {
     match *piece {
@@ -213,8 +248,7 @@ fn available_moves(square: &Square, piece: &ColorPiece) -> Vec<Square> {
                  square.down_left(),
                  square.down_right()]
                 .into_iter()
-                .filter(|s| s.is_some())
-                .map(|s| s.unwrap())
+                .flatten()
                 .collect()
         }
         (_, Piece::Knight) => {
@@ -227,8 +261,7 @@ fn available_moves(square: &Square, piece: &ColorPiece) -> Vec<Square> {
                  square.neighboor(-1, 2),
                  square.neighboor(-1, -2)]
                 .into_iter()
-                .filter(|s| s.is_some())
-                .map(|s| s.unwrap())
+                .flatten()
                 .collect()
         }
         (color, Piece::Pawn) => {
@@ -238,14 +271,14 @@ fn available_moves(square: &Square, piece: &ColorPiece) -> Vec<Square> {
                     if square.rank == 7 {
                         moves.push(square.neighboor(0, -2));
                     }
-                    moves.into_iter().filter(|s| s.is_some()).map(|s| s.unwrap()).collect()
+                    moves.into_iter().flatten().collect()
                 }
                 Color::White => {
                     let mut moves = vec![square.up(), square.up_left(), square.up_right()];
                     if square.rank == 2 {
                         moves.push(square.neighboor(0, 2));
                     }
-                    moves.into_iter().filter(|s| s.is_some()).map(|s| s.unwrap()).collect()
+                    moves.into_iter().flatten().collect()
                 }
             }
         }
@@ -288,13 +321,13 @@ fn available_moves(square: &Square, piece: &ColorPiece) -> Vec<Square> {
 }
 
 #[derive(Debug)]
-enum GameStatus {
+pub enum GameStatus {
     InPlay,
     Finished(Color),
 }
 
 #[derive(Clone, Copy, Debug)]
-struct Board {
+pub struct Board {
     squares: [[ColorPiece; 8]; 8],
 }
 
@@ -312,26 +345,107 @@ impl Board {
         board
     }
 
+    // The Chess960 start position numbered `index` by Scharnagl's scheme, in which
+    // the standard one is 518
+    fn chess960(index: usize) -> Board {
+        // the knights' places among the five files left after the bishops and queen
+        const KNIGHTS: [(usize, usize); 10] =
+            [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+        fn place(back: &mut [Piece; 8], nth: usize, piece: Piece) {
+            let file = (0..8).filter(|&i| back[i] == Piece::Empty).nth(nth).expect("No file left");
+            back[file] = piece;
+        }
+
+        let index = index % 960;
+        let mut back = [Piece::Empty; 8];
+        back[index % 4 * 2 + 1] = Piece::Bishop;
+        back[index / 4 % 4 * 2] = Piece::Bishop;
+        place(&mut back, index / 16 % 6, Piece::Queen);
+        let (first, second) = KNIGHTS[index / 96];
+        place(&mut back, second, Piece::Knight);
+        place(&mut back, first, Piece::Knight);
+        for &piece in &[Piece::Rook, Piece::King, Piece::Rook] {
+            place(&mut back, 0, piece);
+        }
+
+        let mut board = Board { squares: [[EMPTY; 8]; 8] };
+        for (i, &piece) in back.iter().enumerate() {
+            board.set(Square::from_indexes(i, 0), (Color::White, piece));
+            board.set(Square::from_indexes(i, 7), (Color::Black, piece));
+        }
+        for color in &[Color::Black, Color::White] {
+            board.add_pawns(color);
+        }
+        board
+    }
+
     fn get(&self, square: &Square) -> ColorPiece {
         let (i, j) = square.indexes();
         self.squares[i][j]
     }
 
-    fn score(&self) -> (usize, usize) {
+    fn king(&self, color: Color) -> Option<Square> {
+        for (i, col) in self.squares.iter().enumerate() {
+            for (j, &piece) in col.iter().enumerate() {
+                if piece == (color, Piece::King) {
+                    return Some(Square::from_indexes(i, j));
+                }
+            }
+        }
+        None
+    }
+
+    fn attacks(&self, square: &Square) -> Vec<Square> {
+        let (color, piece) = self.get(square);
+        available_moves(square, &(color, piece))
+            .into_iter()
+            .filter(|to| {
+                match piece {
+                    Piece::Knight => true,
+                    Piece::Pawn => to.file != square.file,
+                    _ => square.in_between(to).iter().all(|s| self.get(s).1 == Piece::Empty),
+                }
+            })
+            .collect()
+    }
+
+    fn is_attacked(&self, square: &Square, by: Color) -> bool {
+        self.squares.iter().enumerate().any(|(i, col)| {
+            col.iter().enumerate().any(|(j, &(c, p))| {
+                c == by && p != Piece::Empty &&
+                self.attacks(&Square::from_indexes(i, j)).contains(square)
+            })
+        })
+    }
+
+    fn in_check(&self, color: Color) -> bool {
+        match self.king(color) {
+            Some(king) => self.is_attacked(&king, color.other()),
+            None => false,
+        }
+    }
+
+    fn has_non_pawn_material(&self, color: Color) -> bool {
+        self.squares.iter().flatten().any(|&(c, p)| {
+            c == color && p != Piece::Empty && p != Piece::Pawn && p != Piece::King
+        })
+    }
+
+    fn score(&self, params: &Params) -> (i32, i32) {
         if let GameStatus::Finished(color) = self.status() {
             return match color {
-                Color::Black => (0, 100),
-                Color::White => (100, 0),
+                Color::Black => (0, MATE),
+                Color::White => (MATE, 0),
             };
         }
 
         self.squares
             .iter()
-            .flat_map(|c| c)
+            .flatten()
             .fold((0, 0), |(w_score, b_score), &(c, p)| {
                 match c {
-                    Color::Black => (w_score, b_score + p.value()),
-                    Color::White => (w_score + p.value(), b_score),
+                    Color::Black => (w_score, b_score + params.value(p)),
+                    Color::White => (w_score + params.value(p), b_score),
                 }
             })
     }
@@ -339,7 +453,7 @@ impl Board {
     fn status(&self) -> GameStatus {
         let kings = self.squares
             .iter()
-            .flat_map(|c| c)
+            .flatten()
             .fold((false, false), |(w_has_king, b_has_king), &(c, p)| {
                 match c {
                     Color::Black => (w_has_king, b_has_king || p == Piece::King),
@@ -360,7 +474,7 @@ impl Board {
             .iter()
             .enumerate()
             .flat_map(|(i, col)| {
-                col.into_iter()
+                col.iter()
                     .enumerate()
                     .map(|(j, p)| (Square::from_indexes(i, j), *p))
                     .collect::<Vec<(Square, ColorPiece)>>()
@@ -409,12 +523,7 @@ impl Board {
         if from_piece != Piece::Knight {
             let in_between = from.in_between(to);
             let with_pieces = in_between.iter()
-                .filter(|s| {
-                    match self.get(s) {
-                        (_, Piece::Empty) => false,
-                        _ => true,
-                    }
-                })
+                .filter(|s| self.get(s).1 != Piece::Empty)
                 .collect::<Vec<&Square>>();
             if !with_pieces.is_empty() {
                 return false;
@@ -501,14 +610,14 @@ impl Board {
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(write!(f, "     a  b  c  d  e  f  g  h\n"));
-        try!(write!(f, "   -------------------------\n"));
+        writeln!(f, "     a  b  c  d  e  f  g  h")?;
+        writeln!(f, "   -------------------------")?;
         for (j, rank) in RANKS.iter().enumerate().rev() {
-            try!(write!(f, "{} | ", rank));
+            write!(f, "{} | ", rank)?;
 
             for (i, _) in FILES.iter().enumerate() {
-                let c = match self.squares[i][j] {
-                    (__, Piece::Empty) => ' ',
+                let symbol = match self.squares[i][j] {
+                    (_, Piece::Empty) => ' ',
                     (Color::White, Piece::Bishop) => '♗',
                     (Color::White, Piece::King) => '♔',
                     (Color::White, Piece::Knight) => '♘',
@@ -522,28 +631,29 @@ impl fmt::Display for Board {
                     (Color::Black, Piece::Queen) => '♛',
                     (Color::Black, Piece::Rook) => '♜',
                 };
-                try!(write!(f, " {} ", c))
+                write!(f, " {} ", symbol)?
             }
-            try!(write!(f, "| {}", rank));
-            try!(write!(f, "\n"))
+            write!(f, "| {}", rank)?;
+            writeln!(f)?
         }
-        try!(write!(f, "   -------------------------\n"));
-        try!(write!(f, "     a  b  c  d  e  f  g  h\n"));
+        writeln!(f, "   -------------------------")?;
+        writeln!(f, "     a  b  c  d  e  f  g  h")?;
         Ok(())
     }
 }
 
-struct GameTreeNode {
+pub struct GameTreeNode {
     board: Board,
     turn: Color,
     size: usize,
-    children: HashMap<Move, Option<GameTreeNode>>,
+    // ordered so a game replays identically from the same seed
+    children: BTreeMap<Move, Option<GameTreeNode>>,
 }
 
 impl GameTreeNode {
-    fn new(board: Board, turn: Color, size: usize) -> GameTreeNode {
+    fn new(board: Board, turn: Color, size: usize, rng: &mut Isaac64Rng) -> GameTreeNode {
         let mut legal_moves = board.legal_moves(turn);
-        rand::thread_rng().shuffle(&mut legal_moves);
+        rng.shuffle(&mut legal_moves);
 
         GameTreeNode {
             board: board,
@@ -566,54 +676,130 @@ impl GameTreeNode {
             return 1;
         }
 
-        executed.iter().map(|c| c.size()).fold(0, |acc, size| acc + size)
+        executed.iter().map(|c| c.size()).sum()
     }
 
-    fn exec_random_moves(&mut self, depth: usize, pool: Option<&CpuPool>) {
+    // Samples more moves until `size` of them are tracked, keeping the explored ones
+    fn widen(&mut self, size: usize, rng: &mut Isaac64Rng) {
+        self.size = size;
+        if self.children.len() >= size {
+            return;
+        }
+
+        let mut legal_moves = self.board.legal_moves(self.turn);
+        rng.shuffle(&mut legal_moves);
+        for cmove in legal_moves {
+            if self.children.len() >= size {
+                break;
+            }
+            self.children.entry(cmove).or_insert(None);
+        }
+    }
+
+    // Finds the node for `board` among this one and those up to `plies` moves
+    // below it, so the tree can follow the game instead of being rebuilt
+    fn reroot(self, board: &Board, turn: Color, plies: usize) -> Option<GameTreeNode> {
+        if self.turn == turn && self.board.hash(turn) == board.hash(turn) {
+            return Some(self);
+        }
+        if plies == 0 {
+            return None;
+        }
+
+        self.children
+            .into_values()
+            .flatten()
+            .filter_map(|node| node.reroot(board, turn, plies - 1))
+            .next()
+    }
+
+    // The root's moves are sampled on `threads` tasks of `pool` when one is given,
+    // each from its own seed so the tree does not depend on how many there are
+    fn exec_random_moves(&mut self,
+                         depth: usize,
+                         rng: &mut Isaac64Rng,
+                         pool: Option<(&CpuPool, usize)>) {
         if let GameStatus::Finished(_) = self.board.status() {
             return;
         }
 
         let runs = self.size / 2;
         let new_depth = depth - 1;
-        let color = self.turn.other();
 
         if new_depth == 0 {
             return;
         }
 
-        if let Some(pool) = pool {
-            let mut futures = vec![];
-
-            for &(from, to) in self.children.keys() {
-                let board = self.board;
-
-                let future: CpuFuture<(Move, GameTreeNode), ()> = pool.spawn_fn(move || {
-                    let new_state = board.exec_move(&from, &to);
-                    let mut node = GameTreeNode::new(new_state, color, runs);
-                    node.exec_random_moves(new_depth, None);
-                    future::ok(((from, to), node))
-                });
-                futures.push(future)
+        if let Some((pool, threads)) = pool {
+            let threads = threads.max(1);
+            let mut tasks = (0..threads).map(|_| vec![]).collect::<Vec<Vec<_>>>();
+            for (i, (&cmove, node)) in self.children.iter_mut().enumerate() {
+                tasks[i % threads].push((cmove, node.take(), rng.gen::<u64>()));
             }
 
+            let board = self.board;
+            let futures = tasks.into_iter()
+                .map(|task| {
+                    pool.spawn_fn(move || -> Result<Vec<(Move, GameTreeNode)>, ()> {
+                        Ok(task.into_iter()
+                            .map(|(cmove, node, seed)| {
+                                let mut rng = Isaac64Rng::from_seed(&[seed]);
+                                let child = GameTreeNode::sample_child(board,
+                                                                       cmove,
+                                                                       node,
+                                                                       runs,
+                                                                       new_depth,
+                                                                       &mut rng);
+                                (cmove, child)
+                            })
+                            .collect())
+                    })
+                })
+                .collect::<Vec<_>>();
+
             for future in futures {
-                match future.wait() {
-                    Ok((cmove, node)) => self.children.insert(cmove, Some(node)),
-                    Err(_) => panic!("Failed future"),
-                };
+                for (cmove, child) in future.wait().expect("Failed future") {
+                    self.children.insert(cmove, Some(child));
+                }
             }
         } else {
-            for (&(from, to), node) in &mut self.children {
-                let new_state = self.board.exec_move(&from, &to);
-                let mut new_node = GameTreeNode::new(new_state, color, runs);
-                new_node.exec_random_moves(new_depth, None);
-                *node = Some(new_node)
+            for (&cmove, node) in &mut self.children {
+                let child = GameTreeNode::sample_child(self.board,
+                                                       cmove,
+                                                       node.take(),
+                                                       runs,
+                                                       new_depth,
+                                                       rng);
+                *node = Some(child)
             }
         }
     }
 
-    fn avg_score(&self, color: Color) -> f64 {
+    // Explores further below the child reached by `cmove` from `board`, first
+    // creating it if it was not sampled before
+    fn sample_child(board: Board,
+                    cmove: Move,
+                    node: Option<GameTreeNode>,
+                    runs: usize,
+                    depth: usize,
+                    rng: &mut Isaac64Rng)
+                    -> GameTreeNode {
+        let mut child = match node {
+            Some(mut child) => {
+                child.widen(runs, rng);
+                child
+            }
+            None => {
+                let (from, to) = cmove;
+                let turn = board.get(&from).0.other();
+                GameTreeNode::new(board.exec_move(&from, &to), turn, runs, rng)
+            }
+        };
+        child.exec_random_moves(depth, rng, None);
+        child
+    }
+
+    fn avg_score(&self, color: Color, evaluator: &mut Evaluator) -> f64 {
         let executed = self.children
             .values()
             .filter(|v| v.is_some())
@@ -624,8 +810,8 @@ impl GameTreeNode {
             .collect::<Vec<&GameTreeNode>>();
 
         let score = match color {
-            Color::Black => self.board.score().1 as f64 - self.board.score().0 as f64,
-            Color::White => self.board.score().0 as f64 - self.board.score().1 as f64,
+            Color::Black => -evaluator.evaluate(&self.board, self.turn) as f64,
+            Color::White => evaluator.evaluate(&self.board, self.turn) as f64,
         };
 
         if executed.is_empty() {
@@ -633,29 +819,73 @@ impl GameTreeNode {
         }
 
         let (sum, count) = executed.iter()
-            .map(|node| node.avg_score(color))
+            .map(|node| node.avg_score(color, evaluator))
             .fold((score, 1), |(sum, count), score| (sum + score, count + 1));
 
         if count == 0 {
             -100.0
         } else {
-            sum as f64 / count as f64
+            sum / count as f64
+        }
+    }
+
+    // Explored children with their average score for `color`, best first
+    fn ranked(&self, color: Color, evaluator: &mut Evaluator) -> Vec<(Move, f64)> {
+        let mut ranked = self.children
+            .iter()
+            .filter_map(|(&cmove, node)| {
+                node.as_ref().map(|node| (cmove, node.avg_score(color, evaluator)))
+            })
+            .collect::<Vec<(Move, f64)>>();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        ranked
+    }
+
+    // The best explored move for the side to move at each level below this node
+    fn principal_variation(&self, evaluator: &mut Evaluator) -> Vec<Move> {
+        let best = match self.ranked(self.turn, evaluator).first() {
+            Some(&(cmove, _)) => cmove,
+            None => return vec![],
+        };
+
+        let mut line = vec![best];
+        if let Some(&Some(ref node)) = self.children.get(&best) {
+            line.extend(node.principal_variation(evaluator));
         }
+        line
     }
 }
 
-fn next_move(board: Board, turn: Color, pool: &CpuPool) -> Option<Move> {
-    let mut tree = GameTreeNode::new(board, turn, 64);
-    tree.exec_random_moves(5, Some(pool));
+// `tree` is what was kept from the previous call: the subtree under the move
+// played then, which is re-rooted on the opponent's reply when it was sampled
+#[allow(clippy::too_many_arguments)]
+fn next_move(tree: &mut Option<GameTreeNode>,
+             board: Board,
+             turn: Color,
+             depth: usize,
+             evaluator: &mut Evaluator,
+             rng: &mut Isaac64Rng,
+             pool: (&CpuPool, usize),
+             quiet: bool)
+             -> Option<Move> {
+    let (mut root, reused) = match tree.take().and_then(|node| node.reroot(&board, turn, 1)) {
+        Some(mut node) => {
+            let reused = node.size();
+            node.widen(64, rng);
+            (node, reused)
+        }
+        None => (GameTreeNode::new(board, turn, 64, rng), 0),
+    };
+    root.exec_random_moves(depth, rng, Some(pool));
 
-    let mut max_avg_score = -1000.0_f64;
+    let mut max_avg_score = f64::MIN;
     let mut result = None;
     let mut size = 0;
 
-    for (cmove, node) in tree.children {
-        match node {
-            Some(node) => {
-                let avg_score = node.avg_score(turn);
+    for (&cmove, node) in &root.children {
+        match *node {
+            Some(ref node) => {
+                let avg_score = node.avg_score(turn, evaluator);
                 size += node.size();
                 // println!("{} -> {}   {}", cmove.0, cmove.1, avg_score);
 
@@ -669,42 +899,681 @@ fn next_move(board: Board, turn: Color, pool: &CpuPool) -> Option<Move> {
     }
 
     if let Some(cmove) = result {
-        println!("turn: {:?}", turn);
-        println!("result: {} -> {}", cmove.0, cmove.1);
-        println!("size: {:?}", size);
+        if !quiet {
+            println!("turn: {:?}", turn);
+            println!("result: {} -> {}", cmove.0, cmove.1);
+            println!("size: {:?}", size);
+            println!("reused: {:?}", reused);
+        }
+        *tree = root.children.remove(&cmove).and_then(|node| node);
     }
     result
 }
 
-fn main() {
-    let pool = CpuPool::new_num_cpus();
-    let mut board = Board::new();
+// Multi-PV for the sampling search: the best explored root moves by average score
+fn sample_lines(root: &GameTreeNode,
+                depth: usize,
+                lines: usize,
+                time_ms: u64,
+                evaluator: &mut Evaluator)
+                -> Vec<SearchInfo> {
+    let nodes = root.size();
+    root.ranked(root.turn, evaluator)
+        .into_iter()
+        .take(lines)
+        .enumerate()
+        .map(|(index, (cmove, score))| {
+            let mut pv = vec![cmove];
+            if let Some(&Some(ref node)) = root.children.get(&cmove) {
+                pv.extend(node.principal_variation(evaluator));
+            }
+            SearchInfo {
+                depth: depth,
+                multipv: index + 1,
+                score: score as i32,
+                nodes: nodes,
+                time_ms: time_ms,
+                pv: pv,
+            }
+        })
+        .collect()
+}
+
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|arg| arg.as_str())
+}
+
+// Arguments that are neither `--name` options nor their values
+fn positional(args: &[String]) -> Vec<&str> {
+    let mut result = vec![];
+    let mut skip = false;
+    for arg in args {
+        if skip {
+            skip = false;
+        } else if arg.starts_with("--") {
+            skip = true;
+        } else {
+            result.push(arg.as_str());
+        }
+    }
+    result
+}
+
+fn exit_with(err: String) -> ! {
+    println!("{}", err);
+    process::exit(1);
+}
+
+fn load_params(args: &[String]) -> Params {
+    match option(args, "--params") {
+        Some(path) => Params::load(path).unwrap_or_else(|err| exit_with(err)),
+        None => Params::default(),
+    }
+}
+
+fn eval(fen: &str, params: Params) {
+    let (board, turn) = match Board::from_fen(fen) {
+        Ok(position) => position,
+        Err(err) => exit_with(format!("invalid FEN: {}", err)),
+    };
+
+    let mut evaluator = Evaluator::with_params(params.clone());
     println!("{}", board);
+    println!("{}", evaluator.breakdown(&board));
+    println!("evaluation: {:+.2} (White side, endgame knowledge applied)\n",
+             evaluator.evaluate(&board, turn) as f64 / 100.0);
+
+    for &color in &[Color::White, Color::Black] {
+        let hanging = board.hanging(color, &params)
+            .iter()
+            .map(|square| square.to_string())
+            .collect::<Vec<String>>();
+        if !hanging.is_empty() {
+            println!("hanging {:?} pieces: {}", color, hanging.join(" "));
+        }
+    }
+}
+
+// `export` is a file the sampled tree is written to, with how many levels of it
+fn analyse(fen: &str, config: EngineConfig, export: Option<(&str, usize)>) {
+    let (board, turn) = match Board::from_fen(fen) {
+        Ok(position) => position,
+        Err(err) => exit_with(format!("invalid FEN: {}", err)),
+    };
+    println!("{}", board);
+    println!("seed: {}", config.seed);
+
+    let params = config.params.clone();
+    let mut engine = Engine::new(config, CpuPool::new_num_cpus());
+    for line in engine.analyse(board, turn) {
+        let moves = line.pv.iter().map(move_string).collect::<Vec<String>>();
+        println!("{}. {:+.2} {}", line.multipv, line.score as f64 / 100.0, moves.join(" "));
+    }
+
+    if let Some((path, levels)) = export {
+        let mut evaluator = Evaluator::with_params(params);
+        match engine.tree() {
+            Some(tree) => {
+                export::write_tree(tree, path, levels, &mut evaluator)
+                    .unwrap_or_else(|err| exit_with(err))
+            }
+            None => exit_with("exporting the tree needs --search sampling".to_string()),
+        }
+        println!("tree written to {}", path);
+    }
+}
+
+// PGN files, or directories searched for them
+fn pgn_files(paths: &[&str]) -> Vec<String> {
+    let mut files = vec![];
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                let mut children = entries.filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path().to_string_lossy().into_owned())
+                    .collect::<Vec<String>>();
+                children.sort();
+                let nested = children.iter()
+                    .filter(|child| Path::new(child).is_dir() || child.ends_with(".pgn"))
+                    .map(|child| child.as_str())
+                    .collect::<Vec<&str>>();
+                files.extend(pgn_files(&nested));
+            }
+            Err(_) => files.push(path.to_string()),
+        }
+    }
+    files
+}
+
+fn make_book(paths: &[&str], out: &str, tree_out: &str, min_count: usize, max_ply: usize) {
+    let mut tree = OpeningTree::new(max_ply);
+    let (mut games, mut skipped) = (0, 0);
+    for path in pgn_files(paths) {
+        for game in pgn::read_games(&path).unwrap_or_else(|err| exit_with(err)) {
+            games += 1;
+            if let Err(err) = tree.add_game(&game) {
+                skipped += 1;
+                println!("{}: game {}: {}", path, games, err);
+            }
+        }
+    }
+    println!("games: {} ({} not fully replayed)", games, skipped);
+
+    tree.book(min_count).save(out).unwrap_or_else(|err| exit_with(err));
+    tree.save_tree(tree_out, min_count).unwrap_or_else(|err| exit_with(err));
+    println!("book written to {}, tree to {}", out, tree_out);
+}
+
+fn epd(path: &str, config: EngineConfig, limit: Limit) {
+    let positions = epd::read_positions(path).unwrap_or_else(|err| exit_with(err));
+    let mut smp = LazySmp::new(config.threads,
+                               config.params.clone(),
+                               config.search,
+                               CpuPool::new_num_cpus());
+    smp.set_quiet(true);
+
+    let (mut solved, mut points, mut max_points, mut total_ms) = (0, 0, 0, 0);
+    for position in &positions {
+        // cleared for each position, so results do not depend on the order
+        smp.clear();
+        let outcome = epd::solve(&mut smp, position, limit);
+
+        let expected = if position.best.is_empty() {
+            format!("am {}", position.avoid.join(" "))
+        } else {
+            format!("bm {}", position.best.join(" "))
+        };
+        let time = outcome.time_ms.map_or("-".to_string(), |ms| format!("{} ms", ms));
+        println!("{:<12} {:<6} {:<5} depth {:<3} {:>9}  {}",
+                 position.id,
+                 if outcome.solved { "solved" } else { "failed" },
+                 outcome.best.as_ref().map_or("none".to_string(), move_string),
+                 outcome.depth,
+                 time,
+                 expected);
+
+        if outcome.solved {
+            solved += 1;
+            total_ms += outcome.time_ms.unwrap_or(0);
+        }
+        points += outcome.score.0;
+        max_points += outcome.score.1;
+    }
+
+    println!("solved: {}/{} ({:.1}%)",
+             solved,
+             positions.len(),
+             100.0 * solved as f64 / positions.len().max(1) as f64);
+    println!("score: {}/{}", points, max_points);
+    println!("time to solution: {} ms total, {} ms average",
+             total_ms,
+             total_ms / (solved as u64).max(1));
+}
+
+// A match player from its own options: `--cmd PATH` starts an external UCI
+// engine searching to `--depth` or for `--movetime` ms, anything else
+// configures this engine as on the command line
+// Engines of the same process share `pool`
+fn player(spec: &str, pool: &CpuPool) -> Player {
+    let args = spec.split_whitespace().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    let name = option(&args, "--name").map(|name| name.to_string());
+    match option(&args, "--cmd") {
+        Some(command) => {
+            let go = match (option(&args, "--depth"), option(&args, "--movetime")) {
+                (_, Some(ms)) => format!("go movetime {}", ms),
+                (Some(depth), None) => format!("go depth {}", depth),
+                (None, None) => "go movetime 1000".to_string(),
+            };
+            let mut engine = UciEngine::start(command, go).unwrap_or_else(|err| exit_with(err));
+            if let Some(name) = name {
+                engine.name = name;
+            }
+            Player::External(engine)
+        }
+        None => {
+            let mut config = engine_config(&args, load_params(&args), Algorithm::Sampling);
+            config.quiet = true;
+            let name = name.unwrap_or_else(|| format!("chess {}", spec).trim().to_string());
+            Player::Internal(name, Box::new(Engine::new(config, pool.clone())))
+        }
+    }
+}
+
+fn time_control(args: &[String]) -> Option<TimeControl> {
+    option(args, "--time-control")
+        .map(|text| TimeControl::parse(text).unwrap_or_else(|err| exit_with(err)))
+}
+
+// `--chess960 N` for the start position numbered N, `--chess960 random` for
+// any of them
+// `random` draws from `rng`, so a run is repeated by giving the same --seed
+fn chess960(args: &[String], rng: &mut Isaac64Rng) -> Option<Replay> {
+    option(args, "--chess960").map(|index| {
+        if index == "random" {
+            return Replay::chess960(rng.gen_range(0, 960));
+        }
+        match index.parse() {
+            Ok(index) if index < 960 => Replay::chess960(index),
+            _ => exit_with(format!("invalid --chess960 '{}', expected 0-959 or random", index)),
+        }
+    })
+}
+
+fn play_match(players: [Player; 2],
+              games: usize,
+              openings: Vec<Replay>,
+              adjudication: Adjudication,
+              sprt: Option<Sprt>,
+              time_control: Option<TimeControl>) {
+    println!("{} vs {}, {} games", players[0].name(), players[1].name(), games);
+
+    let mut tournament = Match::new(players, openings, adjudication);
+    if let Some(time_control) = time_control {
+        println!("time control: {}", time_control);
+        tournament.set_time_control(time_control);
+    }
+    if let Some(sprt) = sprt {
+        let (lower, upper) = sprt.bounds();
+        println!("sprt: elo0 {} elo1 {} alpha {} beta {}, llr bounds ({:.2}, {:.2})",
+                 sprt.elo0,
+                 sprt.elo1,
+                 sprt.alpha,
+                 sprt.beta,
+                 lower,
+                 upper);
+        tournament.set_sprt(sprt);
+    }
+    let results = tournament.run(games).unwrap_or_else(|err| exit_with(err));
+    println!("games: {}", results.games());
+    println!("result: {}", results);
+    if let Some(sprt) = sprt {
+        println!("pentanomial: {:?}", results.pairs);
+        match results.verdict {
+            Some(verdict) => println!("sprt: {}", verdict),
+            None => {
+                println!("sprt: inconclusive, llr {:.2} after {} games",
+                         sprt.llr(&results.pairs),
+                         results.games())
+            }
+        }
+    }
+}
+
+// Moves are read in SAN or UCI notation, along with commands to step through
+// the game, let the engine move and print it
+fn game(path: Option<&str>, chess960: Option<Replay>, mut config: EngineConfig) {
+    let mut game = match path {
+        Some(path) => {
+            let games = pgn::read_games(path).unwrap_or_else(|err| exit_with(err));
+            match games.first() {
+                Some(pgn) => Game::from_pgn(pgn).unwrap_or_else(|err| exit_with(err)),
+                None => exit_with(format!("{}: no games", path)),
+            }
+        }
+        None => Game::from_replay(chess960.unwrap_or_else(Replay::new)),
+    };
+    config.quiet = true;
+    let mut engine = Engine::new(config, CpuPool::new_num_cpus());
+    println!("{}", game.board());
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.expect("Failed to read stdin");
+        let mut words = line.splitn(2, ' ');
+        let command = words.next().unwrap_or("");
+        let rest = words.next().unwrap_or("").trim();
+        let turn = game.turn();
+        let changed = match command {
+            "" => false,
+            "quit" => break,
+            "undo" => game.undo(),
+            "redo" => game.redo(),
+            "goto" => rest.parse().map(|ply| game.go_to(ply)).unwrap_or(false),
+            "moves" => {
+                let position = game.position();
+                let moves = game.legal_moves()
+                    .iter()
+                    .map(|cmove| position.san(cmove, None))
+                    .collect::<Vec<String>>();
+                println!("{}", moves.join(" "));
+                false
+            }
+            "history" => {
+                for (ply, record) in game.moves().iter().enumerate() {
+                    let current = if ply + 1 == game.ply() { "*" } else { "" };
+                    println!("{}{} {}{}",
+                             ply + 1,
+                             current,
+                             record.san,
+                             record.eval.map_or(String::new(), |cp| format!(" ({})", cp)));
+                }
+                false
+            }
+            "comment" => {
+                if let Some(record) = game.last_mut() {
+                    record.comment = Some(rest.to_string());
+                }
+                false
+            }
+            "fen" => {
+                println!("{}", game.position().to_fen(false));
+                false
+            }
+            "pgn" => {
+                print!("{}", game.to_pgn());
+                false
+            }
+            "go" => {
+                let board = game.board();
+                match engine.next_move(board, turn, None).map(|cmove| game.play(cmove, None)) {
+                    Some(Ok(record)) => {
+                        record.eval = engine.score();
+                        println!("{}", record.san);
+                        true
+                    }
+                    _ => {
+                        println!("no move");
+                        false
+                    }
+                }
+            }
+            token => {
+                let played = match uci::parse_move(token) {
+                    Ok(cmove) => game.play(cmove, uci::parse_promotion(token)).map(|_| ()),
+                    Err(_) => {
+                        game.position()
+                            .parse_san(token)
+                            .and_then(|(cmove, promotion)| game.play(cmove, promotion).map(|_| ()))
+                    }
+                };
+                match played {
+                    Ok(()) => true,
+                    Err(err) => {
+                        println!("{}", err);
+                        false
+                    }
+                }
+            }
+        };
+
+        if changed {
+            println!("{}", game.board());
+            println!("ply {} of {}", game.ply(), game.moves().len());
+            if let Some((result, termination)) = game.outcome() {
+                println!("{} ({})", result, termination);
+            }
+        }
+    }
+}
+
+fn tune(path: &str, mut params: Params, out: &str, iterations: usize) {
+    let positions = tune::load_positions(path).unwrap_or_else(|err| exit_with(err));
+    println!("positions: {}", positions.len());
+
+    let mut tuner = Tuner::new(positions, CpuPool::new_num_cpus())
+        .unwrap_or_else(|err| exit_with(err));
+    println!("k: {:.2}", tuner.fit_k(&params));
+
+    match tuner.tune(&mut params, iterations, out) {
+        Ok(error) => println!("final error: {:.6}, saved to {}", error, out),
+        Err(err) => exit_with(err),
+    }
+}
+
+fn seed(args: &[String]) -> Option<u64> {
+    option(args, "--seed").map(|seed| {
+        seed.parse().unwrap_or_else(|_| exit_with(format!("invalid seed '{}'", seed)))
+    })
+}
+
+// `algorithm` is used unless --search names another
+fn engine_config(args: &[String], params: Params, algorithm: Algorithm) -> EngineConfig {
+    let algorithm = match option(args, "--search") {
+        Some(name) => {
+            Algorithm::from_name(name)
+                .unwrap_or_else(|| exit_with(format!("unknown search algorithm '{}'", name)))
+        }
+        None => algorithm,
+    };
+
+    let mut config = EngineConfig::new(algorithm, params);
+    if let Some(depth) = option(args, "--depth").and_then(|n| n.parse().ok()) {
+        config.depth = depth;
+    }
+    if let Some(threads) = option(args, "--threads").and_then(|n| n.parse().ok()) {
+        config.threads = threads;
+    }
+    if let Some(lines) = option(args, "--lines").and_then(|n| n.parse().ok()) {
+        config.lines = lines;
+    }
+    if let Some(path) = option(args, "--book") {
+        config.book = Some(Arc::new(Book::open(path).unwrap_or_else(|err| exit_with(err))));
+    }
+    if let Some(name) = option(args, "--book-select") {
+        config.book_selection = Selection::from_name(name)
+            .unwrap_or_else(|| exit_with(format!("unknown book selection '{}'", name)));
+    }
+    config.seed = seed(args).unwrap_or(config.seed);
+    if let Some(names) = option(args, "--disable") {
+        for name in names.split(',') {
+            config.search.disable(name).unwrap_or_else(|err| exit_with(err));
+        }
+    }
+    config
+}
+
+// uci and epd need the time limits and stopping of alpha-beta, which the
+// sampling tree does not have
+fn alpha_beta_config(args: &[String], params: Params, command: &str) -> EngineConfig {
+    let config = engine_config(args, params, Algorithm::AlphaBeta);
+    if config.algorithm != Algorithm::AlphaBeta {
+        exit_with(format!("{} only searches with alphabeta", command));
+    }
+    config
+}
+
+fn self_play(config: EngineConfig, time_control: Option<TimeControl>, start: Replay) {
+    println!("seed: {}", config.seed);
+    let params = config.params.clone();
+    let mut evaluator = Evaluator::with_params(params.clone());
+    let mut engine = Engine::new(config, CpuPool::new_num_cpus());
+    let mut game = Game::from_replay(start);
+    game.set_tag("Event", "self play");
+    println!("{}", game.board());
+
+    let mut clock = time_control.map(Clock::new);
+    if let Some(ref clock) = clock {
+        println!("clock: {}", clock);
+        game.set_tag("TimeControl", &clock.to_string());
+    }
 
     let start = time::precise_time_ns();
     let mut turn_count = 0;
-    let mut turn = Color::White;
 
     loop {
+        if let Some((result, termination)) = game.outcome() {
+            game.finish(result, termination);
+            break;
+        }
         turn_count += 1;
+        let turn = game.turn();
+        let loss = GameResult::win_for(turn.other());
 
-        if let Some((from, to)) = next_move(board, turn, &pool) {
-            board = board.exec_move(&from, &to);
-
-            // print!("{}[2J", 27 as char);
-            println!("{}", board);
-            println!("board.score(): {:?}", board.score());
-            println!("board.status(): {:?}", board.status());
+        if let Some(ref mut clock) = clock {
+            clock.start();
+        }
+        let cmove = engine.next_move(game.board(), turn, clock.as_ref());
+        if let Some(ref mut clock) = clock {
+            if !clock.stop(turn) {
+                println!("{:?} lost on time", turn);
+                game.finish(loss, Termination::Time);
+                break;
+            }
+            println!("clock: {}", clock);
+        }
 
-            if let GameStatus::Finished(_) = board.status() {
+        let cmove = match cmove {
+            Some(cmove) => cmove,
+            None => {
+                game.finish(loss, Termination::NoMove);
+                break;
+            }
+        };
+        match game.play(cmove, None) {
+            Ok(record) => {
+                record.eval = engine.score();
+                record.clock_ms = clock.as_ref().map(|clock| clock.remaining(turn));
+            }
+            Err(err) => {
+                println!("{:?}: {}", turn, err);
+                game.finish(loss, Termination::IllegalMove);
                 break;
             }
         }
-        turn = turn.other();
+
+        let board = game.board();
+        // print!("{}[2J", 27 as char);
+        println!("{}", board);
+        println!("board.score(): {:?}", board.score(&params));
+        println!("evaluation: {}", evaluator.evaluate(&board, turn.other()));
+        println!("board.status(): {:?}", board.status());
     }
 
-    let total_time_s = (time::precise_time_ns() - start) as f64 / 1000000000 as f64;
+    println!("{}", game.to_pgn());
+    let total_time_s = (time::precise_time_ns() - start) as f64 / 1000000000.0;
     println!("turns: {:?}", turn_count);
     println!("time (s): {:.*}", 5, total_time_s);
     println!("turns/s: {:.*}", 5, turn_count as f64 / total_time_s);
 }
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    let params = load_params(&args);
+
+    match args.first().map(|arg| arg.as_str()) {
+        Some("eval") => eval(&positional(&args[1..]).join(" "), params),
+        Some("tune") => {
+            let path = positional(&args[1..])
+                .first()
+                .cloned()
+                .unwrap_or_else(|| exit_with("usage: tune <positions> [--out FILE]".to_string()));
+            let iterations = option(&args, "--iterations")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(100);
+            tune(path, params, option(&args, "--out").unwrap_or("params.txt"), iterations)
+        }
+        Some("make-book") => {
+            let paths = positional(&args[1..]);
+            if paths.is_empty() {
+                exit_with("usage: make-book <pgn files or directories> [--out FILE] [--tree FILE] \
+                           [--min-count N] [--max-ply N]"
+                    .to_string());
+            }
+            let number = |name, default| {
+                option(&args, name).and_then(|n| n.parse().ok()).unwrap_or(default)
+            };
+            make_book(&paths,
+                      option(&args, "--out").unwrap_or("book.bin"),
+                      option(&args, "--tree").unwrap_or("book.txt"),
+                      number("--min-count", 1),
+                      number("--max-ply", 20))
+        }
+        Some("analyse") => {
+            let levels = option(&args, "--levels").and_then(|n| n.parse().ok()).unwrap_or(2);
+            analyse(&positional(&args[1..]).join(" "),
+                    engine_config(&args[1..], params, Algorithm::Sampling),
+                    option(&args, "--export").map(|path| (path, levels)))
+        }
+        Some("epd") => {
+            let path = positional(&args[1..])
+                .first()
+                .cloned()
+                .unwrap_or_else(|| {
+                    exit_with("usage: epd <file> [--depth N | --time MS]".to_string())
+                });
+            let config = alpha_beta_config(&args[1..], params, "epd");
+            let limit = match option(&args, "--time").map(|ms| ms.parse()) {
+                Some(Ok(ms)) => Limit::Time(ms),
+                Some(Err(_)) => exit_with("invalid --time".to_string()),
+                None => Limit::Depth(config.depth),
+            };
+            epd(path, config, limit)
+        }
+        Some("match") => {
+            let spec = |name| {
+                option(&args, name).unwrap_or_else(|| {
+                    exit_with("usage: match --first SPEC --second SPEC [--games N] \
+                               [--openings FILE] [--adjudicate CP] [--adjudicate-moves N] \
+                               [--known-endgame on|off] [--sprt ELO0,ELO1] [--alpha A] [--beta B] \
+                               [--time-control TC] [--chess960 N|random]"
+                        .to_string())
+                })
+            };
+            let number = |name, default| {
+                option(&args, name).and_then(|n| n.parse().ok()).unwrap_or(default)
+            };
+            let adjudication = Adjudication {
+                score: option(&args, "--adjudicate")
+                    .and_then(|cp| cp.parse().ok())
+                    .map(|cp| (cp, number("--adjudicate-moves", 3))),
+                known_endgame: option(&args, "--known-endgame") != Some("off"),
+            };
+            let probability = |name| {
+                option(&args, name).and_then(|p| p.parse().ok()).unwrap_or(0.05)
+            };
+            let sprt = option(&args, "--sprt").map(|bounds| {
+                let elos = bounds.split(',')
+                    .filter_map(|elo| elo.parse().ok())
+                    .collect::<Vec<f64>>();
+                if elos.len() != 2 || bounds.split(',').count() != 2 {
+                    exit_with(format!("invalid --sprt '{}', expected ELO0,ELO1", bounds));
+                }
+                Sprt {
+                    elo0: elos[0],
+                    elo1: elos[1],
+                    alpha: probability("--alpha"),
+                    beta: probability("--beta"),
+                }
+            });
+            // a test runs until it decides, within a generous game limit
+            let games = number("--games", if sprt.is_some() { 100000 } else { 10 });
+            // a random Chess960 position is drawn for each pair
+            let seed = seed(&args).unwrap_or_else(|| rand::thread_rng().gen());
+            let mut rng = Isaac64Rng::from_seed(&[seed]);
+            let openings = match option(&args, "--openings") {
+                Some(path) => tournament::load_openings(path).unwrap_or_else(|err| exit_with(err)),
+                None => {
+                    (0..games.div_ceil(2)).filter_map(|_| chess960(&args, &mut rng)).collect()
+                }
+            };
+            if option(&args, "--chess960") == Some("random") {
+                println!("seed: {}", seed);
+            }
+            let pool = CpuPool::new_num_cpus();
+            play_match([player(spec("--first"), &pool), player(spec("--second"), &pool)],
+                       games,
+                       openings,
+                       adjudication,
+                       sprt,
+                       time_control(&args))
+        }
+        Some("game") => {
+            let config = engine_config(&args[1..], params, Algorithm::Sampling);
+            let mut rng = Isaac64Rng::from_seed(&[config.seed]);
+            game(positional(&args[1..]).first().cloned(),
+                 chess960(&args, &mut rng),
+                 config)
+        }
+        Some("uci") => uci::run(alpha_beta_config(&args[1..], params, "uci")),
+        _ => {
+            let config = engine_config(&args, params, Algorithm::Sampling);
+            let mut rng = Isaac64Rng::from_seed(&[config.seed]);
+            let start = chess960(&args, &mut rng).unwrap_or_else(Replay::new);
+            self_play(config, time_control(&args), start)
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use {Board, Color, Move, Piece};
+use params::Params;
+use see::KING_VALUE;
+
+const MAX_HISTORY: i32 = 1 << 14;
+
+// Butterfly table of quiet move scores indexed by side, from and to squares
+pub struct History {
+    table: Vec<i32>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History { table: vec![0; 2 * 64 * 64] }
+    }
+
+    fn index(color: Color, cmove: &Move) -> usize {
+        (color as usize * 64 + cmove.0.index()) * 64 + cmove.1.index()
+    }
+
+    pub fn get(&self, color: Color, cmove: &Move) -> i32 {
+        self.table[History::index(color, cmove)]
+    }
+
+    fn add(&mut self, color: Color, cmove: &Move, bonus: i32) {
+        // scaled so entries saturate at MAX_HISTORY instead of growing unbounded
+        let entry = &mut self.table[History::index(color, cmove)];
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+
+    // Rewards the quiet move that caused a beta cutoff and penalises the quiet
+    // moves searched before it
+    pub fn update(&mut self, color: Color, best: &Move, tried: &[Move], depth: usize) {
+        let bonus = (depth * depth).min(400) as i32;
+        self.add(color, best, bonus);
+        for cmove in tried {
+            if cmove != best {
+                self.add(color, cmove, -bonus);
+            }
+        }
+    }
+}
+
+pub struct Killers {
+    moves: Vec<[Option<Move>; 2]>,
+}
+
+impl Killers {
+    pub fn new(max_ply: usize) -> Killers {
+        Killers { moves: vec![[None, None]; max_ply] }
+    }
+
+    pub fn get(&self, ply: usize) -> [Option<Move>; 2] {
+        self.moves.get(ply).cloned().unwrap_or([None, None])
+    }
+
+    pub fn store(&mut self, ply: usize, cmove: Move) {
+        if let Some(killers) = self.moves.get_mut(ply) {
+            if killers[0] != Some(cmove) {
+                killers[1] = killers[0];
+                killers[0] = Some(cmove);
+            }
+        }
+    }
+}
+
+// The quiet reply that last refuted each move, indexed by its from and to squares
+pub struct CounterMoves {
+    moves: Vec<Option<Move>>,
+}
+
+impl CounterMoves {
+    pub fn new() -> CounterMoves {
+        CounterMoves { moves: vec![None; 64 * 64] }
+    }
+
+    pub fn get(&self, previous: Option<Move>) -> Option<Move> {
+        previous.and_then(|(from, to)| self.moves[from.index() * 64 + to.index()])
+    }
+
+    pub fn store(&mut self, previous: Option<Move>, cmove: Move) {
+        if let Some((from, to)) = previous {
+            self.moves[from.index() * 64 + to.index()] = Some(cmove);
+        }
+    }
+}
+
+pub fn is_capture(board: &Board, cmove: &Move) -> bool {
+    board.get(&cmove.1).1 != Piece::Empty
+}
+
+// Most valuable victim first, least valuable attacker as a tie-break. Taking
+// the king ends the game, so it outranks everything.
+fn mvv_lva(board: &Board, cmove: &Move, params: &Params) -> i32 {
+    let victim = match board.get(&cmove.1).1 {
+        Piece::King => KING_VALUE,
+        piece => params.value(piece),
+    };
+    victim * 16 - params.value(board.get(&cmove.0).1)
+}
+
+// Only runs the exchange when taking with a more valuable piece
+pub fn good_capture(board: &Board, cmove: &Move, params: &Params) -> bool {
+    let attacker = board.get(&cmove.0).1;
+    let victim = board.get(&cmove.1).1;
+    victim == Piece::King ||
+    (attacker != Piece::King && params.value(victim) >= params.value(attacker)) ||
+    board.see(cmove, params) >= 0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    HashMove,
+    Captures,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// Yields the hash move, then captures that do not lose material by MVV-LVA,
+// then killers and the countermove, then the remaining quiet moves by history
+// score and finally the losing captures
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Option<Move>,
+    captures: Vec<(Move, i32)>,
+    killers: Vec<Move>,
+    quiets: Vec<(Move, i32)>,
+    bad_captures: Vec<(Move, i32)>,
+}
+
+impl MovePicker {
+    pub fn new(board: &Board,
+               turn: Color,
+               hash_move: Option<Move>,
+               killers: [Option<Move>; 2],
+               countermove: Option<Move>,
+               history: &History,
+               params: &Params)
+               -> MovePicker {
+        let moves = board.legal_moves(turn);
+        let hash_move = hash_move.and_then(|m| if moves.contains(&m) { Some(m) } else { None });
+
+        let mut refutations = vec![];
+        for &cmove in killers.iter().chain(&[countermove]) {
+            if let Some(cmove) = cmove {
+                if moves.contains(&cmove) && !is_capture(board, &cmove) &&
+                   Some(cmove) != hash_move && !refutations.contains(&cmove) {
+                    refutations.push(cmove);
+                }
+            }
+        }
+        refutations.reverse();
+
+        let mut captures = vec![];
+        let mut quiets = vec![];
+        let mut bad_captures = vec![];
+        for cmove in moves {
+            if Some(cmove) == hash_move || refutations.contains(&cmove) {
+                continue;
+            }
+            if !is_capture(board, &cmove) {
+                quiets.push((cmove, history.get(turn, &cmove)));
+            } else if good_capture(board, &cmove, params) {
+                captures.push((cmove, mvv_lva(board, &cmove, params)));
+            } else {
+                bad_captures.push((cmove, mvv_lva(board, &cmove, params)));
+            }
+        }
+        captures.sort_by_key(|&(_, score)| score);
+        bad_captures.sort_by_key(|&(_, score)| score);
+
+        MovePicker {
+            stage: Stage::HashMove,
+            hash_move: hash_move,
+            captures: captures,
+            killers: refutations,
+            quiets: quiets,
+            bad_captures: bad_captures,
+        }
+    }
+
+    // Losing captures are left out, as quiescence search never wants them
+    pub fn captures(board: &Board, turn: Color, params: &Params) -> MovePicker {
+        let mut captures = board.legal_moves(turn)
+            .into_iter()
+            .filter(|cmove| is_capture(board, cmove) && good_capture(board, cmove, params))
+            .map(|cmove| (cmove, mvv_lva(board, &cmove, params)))
+            .collect::<Vec<(Move, i32)>>();
+        captures.sort_by_key(|&(_, score)| score);
+
+        MovePicker {
+            stage: Stage::Captures,
+            hash_move: None,
+            captures: captures,
+            killers: vec![],
+            quiets: vec![],
+            bad_captures: vec![],
+        }
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::Captures;
+                    if self.hash_move.is_some() {
+                        return self.hash_move;
+                    }
+                }
+                Stage::Captures => {
+                    match self.captures.pop() {
+                        Some((cmove, _)) => return Some(cmove),
+                        None => self.stage = Stage::Killers,
+                    }
+                }
+                Stage::Killers => {
+                    match self.killers.pop() {
+                        Some(cmove) => return Some(cmove),
+                        None => {
+                            // only sorted once the cheaper stages failed to cut off
+                            self.quiets.sort_by_key(|&(_, score)| score);
+                            self.stage = Stage::Quiets;
+                        }
+                    }
+                }
+                Stage::Quiets => {
+                    match self.quiets.pop() {
+                        Some((cmove, _)) => return Some(cmove),
+                        None => self.stage = Stage::BadCaptures,
+                    }
+                }
+                Stage::BadCaptures => {
+                    match self.bad_captures.pop() {
+                        Some((cmove, _)) => return Some(cmove),
+                        None => self.stage = Stage::Done,
+                    }
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+}
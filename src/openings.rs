@@ -0,0 +1,171 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+
+use Color;
+use book::Book;
+use pgn::Game;
+
+// How a move fared, counted from the side that played it
+#[derive(Clone, Copy, Debug, Default)]
+struct Stats {
+    count: usize,
+    wins: usize,
+    draws: usize,
+    losses: usize,
+    elo_sum: usize,
+    elo_count: usize,
+}
+
+impl Stats {
+    fn add(&mut self, score: Option<f64>, elo: Option<usize>) {
+        self.count += 1;
+        match score {
+            Some(score) if score > 0.75 => self.wins += 1,
+            Some(score) if score < 0.25 => self.losses += 1,
+            Some(_) => self.draws += 1,
+            None => (),
+        }
+        if let Some(elo) = elo {
+            self.elo_sum += elo;
+            self.elo_count += 1;
+        }
+    }
+
+    // Polyglot's usual weighting, two points a win and one a draw
+    fn weight(&self) -> u16 {
+        (2 * self.wins + self.draws).min(u16::MAX as usize) as u16
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} games  +{} ={} -{}",
+               self.count,
+               self.wins,
+               self.draws,
+               self.losses)?;
+        if let Some(elo) = self.elo_sum.checked_div(self.elo_count) {
+            write!(f, "  elo {}", elo)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    stats: Stats,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn write(&self, out: &mut String, ply: usize, min_count: usize) {
+        let mut children = self.children
+            .iter()
+            .filter(|&(_, node)| node.stats.count >= min_count)
+            .collect::<Vec<(&String, &Node)>>();
+        children.sort_by_key(|&(_, node)| Reverse(node.stats.count));
+
+        for (san, node) in children {
+            let number = if ply % 2 == 0 {
+                format!("{}.", ply / 2 + 1)
+            } else {
+                format!("{}...", ply / 2 + 1)
+            };
+            let indent = "  ".repeat(ply);
+            out.push_str(&format!("{}{} {}  {}\n", indent, number, san, node.stats));
+            node.write(out, ply + 1, min_count);
+        }
+    }
+}
+
+// Move statistics gathered from a collection of games, both as the tree of move
+// sequences and per position so transpositions share their counts
+pub struct OpeningTree {
+    max_ply: usize,
+    root: Node,
+    positions: HashMap<u64, HashMap<u16, Stats>>,
+}
+
+impl OpeningTree {
+    pub fn new(max_ply: usize) -> OpeningTree {
+        OpeningTree {
+            max_ply: max_ply,
+            root: Node::default(),
+            positions: HashMap::new(),
+        }
+    }
+
+    // Stops at the first move that cannot be replayed, what came before is kept
+    pub fn add_game(&mut self, game: &Game) -> Result<(), String> {
+        let elo = |tag| game.tag(tag).and_then(|elo| elo.parse().ok());
+        let (white_elo, black_elo) = (elo("WhiteElo"), elo("BlackElo"));
+
+        let mut replay = game.start()?;
+        let mut node = &mut self.root;
+        for san in game.moves.iter().take(self.max_ply) {
+            let (score, elo) = match replay.turn {
+                Color::White => (game.result, white_elo),
+                Color::Black => (game.result.map(|result| 1.0 - result), black_elo),
+            };
+
+            let key = replay.key();
+            let cmove = replay.play_san(san)?;
+            self.positions
+                .entry(key)
+                .or_default()
+                .entry(cmove)
+                .or_default()
+                .add(score, elo);
+
+            let san = san.trim_end_matches(['+', '#', '!', '?']);
+            let child = { node }.children.entry(san.to_string()).or_insert_with(Node::default);
+            child.stats.add(score, elo);
+            node = child;
+        }
+        Ok(())
+    }
+
+    // Moves played at least `min_count` times and not only lost
+    pub fn book(&self, min_count: usize) -> Book {
+        let mut moves = vec![];
+        for (&key, position) in &self.positions {
+            for (&cmove, stats) in position {
+                if stats.count >= min_count && stats.weight() > 0 {
+                    moves.push((key, cmove, stats.weight()));
+                }
+            }
+        }
+        Book::new(moves)
+    }
+
+    pub fn save_tree(&self, path: &str, min_count: usize) -> Result<(), String> {
+        let mut out = String::new();
+        self.root.write(&mut out, 0, min_count);
+        let mut file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        file.write_all(out.as_bytes()).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OpeningTree;
+    use pgn::{self, Replay};
+
+    #[test]
+    fn games_from_a_fen_tag() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let text = format!("[FEN \"{}\"]\n[SetUp \"1\"]\n\n1. e4 Kd7 1-0\n", fen);
+        let mut tree = OpeningTree::new(10);
+        tree.add_game(&pgn::parse_games(&text)[0]).unwrap();
+
+        let mut replay = Replay::from_fen(fen).unwrap();
+        assert!(tree.positions.contains_key(&replay.key()));
+        replay.play_san("e4").unwrap();
+        assert!(tree.positions.contains_key(&replay.key()));
+        assert!(!tree.positions.contains_key(&Replay::new().key()));
+    }
+}
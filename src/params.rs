@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use {Piece, Square};
+use eval::Score;
+
+const PIECE_NAMES: &'static [&'static str] = &["bishop", "empty", "king", "knight", "pawn",
+                                                "queen", "rook"];
+const MATERIAL_PIECES: &'static [Piece] = &[Piece::Bishop,
+                                            Piece::Knight,
+                                            Piece::Pawn,
+                                            Piece::Queen,
+                                            Piece::Rook];
+const PST_PIECES: &'static [Piece] = &[Piece::Bishop,
+                                       Piece::King,
+                                       Piece::Knight,
+                                       Piece::Pawn,
+                                       Piece::Queen,
+                                       Piece::Rook];
+const MOBILITY_PIECES: &'static [Piece] = &[Piece::Bishop,
+                                            Piece::Knight,
+                                            Piece::Queen,
+                                            Piece::Rook];
+
+// Piece-square tables from White's point of view, rank 8 first
+#[rustfmt::skip]
+const PAWN_PST: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_PST: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_PST: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_PST: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_PST: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MG_PST: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_EG_PST: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+#[derive(Clone, Debug)]
+pub struct Params {
+    // indexed by `Piece as usize`
+    pub material: [Score; 7],
+    // 64 entries per piece, from White's point of view with rank 8 first
+    pub pst: Vec<Score>,
+    pub doubled: Score,
+    pub isolated: Score,
+    pub backward: Score,
+    pub chain: Score,
+    // indexed by the rank of the pawn relative to its own side
+    pub passed: [Score; 8],
+    // indexed by how many ranks in front of the king the pawn stands
+    pub shield: [i32; 8],
+    pub shield_missing: i32,
+    pub storm: [i32; 8],
+    pub semi_open_king_file: i32,
+    pub open_king_file: i32,
+    pub attacker_weight: [i32; 7],
+    // percentage of the summed attacker weights applied, indexed by attacker count
+    pub attack_scale: [i32; 8],
+    pub mobility: [Score; 7],
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_seventh: Score,
+    pub knight_outpost: Score,
+    pub bishop_pair: Score,
+    pub bad_bishop_pawn: Score,
+}
+
+impl Default for Params {
+    fn default() -> Params {
+        let mut material = [Score::default(); 7];
+        material[Piece::Bishop as usize] = Score::new(330, 310);
+        material[Piece::Knight as usize] = Score::new(320, 290);
+        material[Piece::Pawn as usize] = Score::new(85, 105);
+        material[Piece::Queen as usize] = Score::new(980, 950);
+        material[Piece::Rook as usize] = Score::new(480, 520);
+
+        let mut pst = vec![Score::default(); 7 * 64];
+        for index in 0..64 {
+            let mut set = |piece: Piece, mg: i32, eg: i32| {
+                pst[piece as usize * 64 + index] = Score::new(mg, eg);
+            };
+            set(Piece::Bishop, BISHOP_PST[index], BISHOP_PST[index]);
+            set(Piece::King, KING_MG_PST[index], KING_EG_PST[index]);
+            set(Piece::Knight, KNIGHT_PST[index], KNIGHT_PST[index]);
+            set(Piece::Pawn, PAWN_PST[index], PAWN_PST[index]);
+            set(Piece::Queen, QUEEN_PST[index], QUEEN_PST[index]);
+            set(Piece::Rook, ROOK_PST[index], ROOK_PST[index]);
+        }
+
+        let mut attacker_weight = [0; 7];
+        attacker_weight[Piece::Bishop as usize] = 20;
+        attacker_weight[Piece::Knight as usize] = 20;
+        attacker_weight[Piece::Queen as usize] = 80;
+        attacker_weight[Piece::Rook as usize] = 40;
+
+        let mut mobility = [Score::default(); 7];
+        mobility[Piece::Bishop as usize] = Score::new(5, 5);
+        mobility[Piece::Knight as usize] = Score::new(4, 4);
+        mobility[Piece::Queen as usize] = Score::new(1, 2);
+        mobility[Piece::Rook as usize] = Score::new(2, 4);
+
+        Params {
+            material: material,
+            pst: pst,
+            doubled: Score::new(-10, -25),
+            isolated: Score::new(-12, -18),
+            backward: Score::new(-10, -14),
+            chain: Score::new(8, 6),
+            passed: [Score::new(0, 0),
+                     Score::new(5, 10),
+                     Score::new(10, 15),
+                     Score::new(15, 30),
+                     Score::new(30, 55),
+                     Score::new(55, 95),
+                     Score::new(90, 150),
+                     Score::new(0, 0)],
+            shield: [0, 25, 12, 4, 0, 0, 0, 0],
+            shield_missing: -20,
+            storm: [0, 10, 30, 20, 10, 0, 0, 0],
+            semi_open_king_file: -15,
+            open_king_file: -25,
+            attacker_weight: attacker_weight,
+            attack_scale: [0, 0, 50, 75, 88, 94, 97, 99],
+            mobility: mobility,
+            rook_open_file: Score::new(20, 10),
+            rook_semi_open_file: Score::new(10, 5),
+            rook_seventh: Score::new(15, 30),
+            knight_outpost: Score::new(25, 10),
+            bishop_pair: Score::new(30, 50),
+            bad_bishop_pawn: Score::new(-3, -6),
+        }
+    }
+}
+
+// Both halves of a score, named `<name>.mg` and `<name>.eg`
+fn visit_score<'a, F>(f: &mut F, name: &dyn Fn() -> String, score: &'a mut Score)
+    where F: FnMut(&dyn Fn() -> String, &'a mut i32)
+{
+    let Score { ref mut mg, ref mut eg } = *score;
+    f(&|| format!("{}.mg", name()), mg);
+    f(&|| format!("{}.eg", name()), eg);
+}
+
+impl Params {
+    pub fn material(&self, piece: Piece) -> Score {
+        self.material[piece as usize]
+    }
+
+    // What a piece is worth when trading it, its middlegame material
+    pub fn value(&self, piece: Piece) -> i32 {
+        self.material[piece as usize].mg
+    }
+
+    pub fn pst(&self, piece: Piece, index: usize) -> Score {
+        self.pst[piece as usize * 64 + index]
+    }
+
+    // Calls `f` with every tunable value and its stable name, in the order they
+    // are saved. Names are only built when `f` asks for them.
+    fn visit<'a, F>(&'a mut self, mut f: F)
+        where F: FnMut(&dyn Fn() -> String, &'a mut i32)
+    {
+        for (piece, score) in self.material.iter_mut().enumerate() {
+            if MATERIAL_PIECES.iter().any(|&p| p as usize == piece) {
+                visit_score(&mut f, &|| format!("material.{}", PIECE_NAMES[piece]), score);
+            }
+        }
+        for (index, score) in self.pst.iter_mut().enumerate() {
+            let (piece, square) = (index / 64, index % 64);
+            if PST_PIECES.iter().any(|&p| p as usize == piece) {
+                let square = Square::from_indexes(square % 8, 7 - square / 8);
+                visit_score(&mut f,
+                            &|| format!("pst.{}.{}", PIECE_NAMES[piece], square),
+                            score);
+            }
+        }
+
+        visit_score(&mut f, &|| "doubled".to_string(), &mut self.doubled);
+        visit_score(&mut f, &|| "isolated".to_string(), &mut self.isolated);
+        visit_score(&mut f, &|| "backward".to_string(), &mut self.backward);
+        visit_score(&mut f, &|| "chain".to_string(), &mut self.chain);
+        for (rank, score) in self.passed.iter_mut().enumerate() {
+            visit_score(&mut f, &|| format!("passed.{}", rank + 1), score);
+        }
+
+        for (distance, value) in self.shield.iter_mut().enumerate() {
+            f(&|| format!("shield.{}", distance), value);
+        }
+        f(&|| "shield_missing".to_string(), &mut self.shield_missing);
+        for (distance, value) in self.storm.iter_mut().enumerate() {
+            f(&|| format!("storm.{}", distance), value);
+        }
+        f(&|| "semi_open_king_file".to_string(), &mut self.semi_open_king_file);
+        f(&|| "open_king_file".to_string(), &mut self.open_king_file);
+        for (piece, value) in self.attacker_weight.iter_mut().enumerate() {
+            if MOBILITY_PIECES.iter().any(|&p| p as usize == piece) {
+                f(&|| format!("attacker_weight.{}", PIECE_NAMES[piece]), value);
+            }
+        }
+        for (count, value) in self.attack_scale.iter_mut().enumerate() {
+            f(&|| format!("attack_scale.{}", count), value);
+        }
+
+        for (piece, score) in self.mobility.iter_mut().enumerate() {
+            if MOBILITY_PIECES.iter().any(|&p| p as usize == piece) {
+                visit_score(&mut f, &|| format!("mobility.{}", PIECE_NAMES[piece]), score);
+            }
+        }
+        visit_score(&mut f, &|| "rook_open_file".to_string(), &mut self.rook_open_file);
+        visit_score(&mut f,
+                    &|| "rook_semi_open_file".to_string(),
+                    &mut self.rook_semi_open_file);
+        visit_score(&mut f, &|| "rook_seventh".to_string(), &mut self.rook_seventh);
+        visit_score(&mut f, &|| "knight_outpost".to_string(), &mut self.knight_outpost);
+        visit_score(&mut f, &|| "bishop_pair".to_string(), &mut self.bishop_pair);
+        visit_score(&mut f, &|| "bad_bishop_pawn".to_string(), &mut self.bad_bishop_pawn);
+    }
+
+    // Every tunable value with its name, in the order they are saved
+    pub fn fields(&mut self) -> Vec<(String, &mut i32)> {
+        let mut fields = vec![];
+        self.visit(|name, value| fields.push((name(), value)));
+        fields
+    }
+
+    // The number of tunable values, which `adjust` indexes in `fields` order
+    pub fn count(&mut self) -> usize {
+        let mut count = 0;
+        self.visit(|_, _| count += 1);
+        count
+    }
+
+    pub fn adjust(&mut self, index: usize, delta: i32) {
+        let mut current = 0;
+        self.visit(|_, value| {
+            if current == index {
+                *value += delta;
+            }
+            current += 1;
+        });
+    }
+
+    // One `name value` pair per line; missing names keep their default
+    pub fn load(path: &str) -> Result<Params, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut values = HashMap::new();
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("{}: {}", path, e))?;
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+            if tokens.len() != 2 {
+                return Err(format!("{}:{}: expected `name value`", path, n + 1));
+            }
+            let value = tokens[1]
+                .parse::<i32>()
+                .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+            values.insert(tokens[0].to_string(), value);
+        }
+
+        let mut params = Params::default();
+        {
+            for (name, value) in params.fields() {
+                if let Some(v) = values.remove(&name) {
+                    *value = v;
+                }
+            }
+        }
+
+        if let Some(name) = values.keys().next() {
+            return Err(format!("{}: unknown parameter '{}'", path, name));
+        }
+        Ok(params)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut params = self.clone();
+        for (name, value) in params.fields() {
+            writeln!(file, "{} {}", name, value).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Params;
+
+    #[test]
+    fn adjust_follows_fields() {
+        let mut params = Params::default();
+        let count = params.count();
+        assert_eq!(count, params.fields().len());
+
+        for &index in &[0, count / 2, count - 1] {
+            let before = *params.fields()[index].1;
+            params.adjust(index, 3);
+            assert_eq!(*params.fields()[index].1, before + 3);
+            params.adjust(index, -3);
+            assert_eq!(*params.fields()[index].1, before);
+        }
+    }
+
+    #[test]
+    fn field_names() {
+        let mut params = Params::default();
+        let names = params.fields().into_iter().map(|(name, _)| name).collect::<Vec<String>>();
+        assert_eq!(names[0], "material.bishop.mg");
+        assert_eq!(names[1], "material.bishop.eg");
+        assert!(names.contains(&"pst.knight.e4.mg".to_string()));
+        assert_eq!(names.last().unwrap(), "bad_bishop_pawn.eg");
+    }
+}
@@ -0,0 +1,147 @@
+use {Board, Color, Piece};
+use eval::{relative_rank, Score};
+use params::Params;
+
+const TABLE_SIZE: usize = 1 << 14;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PawnEntry {
+    key: u64,
+    pub scores: [Score; 2],
+    passed: [u64; 2],
+}
+
+fn bit(i: usize, j: usize) -> u64 {
+    1 << (i * 8 + j)
+}
+
+fn forward(color: Color) -> isize {
+    match color {
+        Color::Black => -1,
+        Color::White => 1,
+    }
+}
+
+impl PawnEntry {
+    fn analyse(params: &Params, board: &Board, key: u64) -> PawnEntry {
+        let mut entry = PawnEntry {
+            key: key,
+            scores: [Score::default(); 2],
+            passed: [0, 0],
+        };
+
+        let is_pawn = |color: Color, i: isize, j: isize| {
+            0 <= i && i <= 7 && 0 <= j && j <= 7 &&
+            board.squares[i as usize][j as usize] == (color, Piece::Pawn)
+        };
+
+        for &color in &[Color::Black, Color::White] {
+            let dir = forward(color);
+            let enemy = color.other();
+            let mut score = Score::default();
+
+            for i in 0..8 {
+                let count = (0..8).filter(|&j| is_pawn(color, i, j)).count() as i32;
+                if count > 1 {
+                    score += params.doubled * (count - 1);
+                }
+
+                for j in 0..8 {
+                    if !is_pawn(color, i, j) {
+                        continue;
+                    }
+
+                    if is_pawn(color, i - 1, j - dir) || is_pawn(color, i + 1, j - dir) {
+                        score += params.chain;
+                    }
+
+                    let ahead = |r: isize| (r - j) * dir > 0;
+                    let passed = !(0..8).any(|r| {
+                        ahead(r) &&
+                        (is_pawn(enemy, i - 1, r) || is_pawn(enemy, i, r) ||
+                         is_pawn(enemy, i + 1, r))
+                    });
+                    if passed {
+                        entry.passed[color as usize] |= bit(i as usize, j as usize);
+                    }
+
+                    let isolated = !(0..8)
+                        .any(|r| is_pawn(color, i - 1, r) || is_pawn(color, i + 1, r));
+                    if isolated {
+                        score += params.isolated;
+                        continue;
+                    }
+
+                    // no neighbour can come up to support it, and the stop square is
+                    // covered by an enemy pawn
+                    let supported = (0..8).any(|r| {
+                        !ahead(r) && (is_pawn(color, i - 1, r) || is_pawn(color, i + 1, r))
+                    });
+                    let stop_attacked = is_pawn(enemy, i - 1, j + 2 * dir) ||
+                                        is_pawn(enemy, i + 1, j + 2 * dir);
+                    if !passed && !supported && stop_attacked {
+                        score += params.backward;
+                    }
+                }
+            }
+
+            entry.scores[color as usize] = score;
+        }
+
+        entry
+    }
+
+    // Depends on pieces other than pawns, so it is recomputed on every probe
+    pub fn passed_score(&self, params: &Params, board: &Board, color: Color) -> Score {
+        let dir = forward(color);
+        let mut score = Score::default();
+
+        for i in 0..8 {
+            for j in 0..8 {
+                if self.passed[color as usize] & bit(i, j) == 0 {
+                    continue;
+                }
+
+                let bonus = params.passed[relative_rank(color, j)];
+                let mut path = vec![];
+                let mut r = j as isize + dir;
+                while 0 <= r && r <= 7 {
+                    path.push(board.squares[i][r as usize].1);
+                    r += dir;
+                }
+
+                score += if path.first().map_or(false, |&p| p != Piece::Empty) {
+                    Score::new(bonus.mg / 2, bonus.eg / 3)
+                } else if path.iter().any(|&p| p != Piece::Empty) {
+                    Score::new(bonus.mg * 3 / 4, bonus.eg * 2 / 3)
+                } else {
+                    bonus
+                };
+            }
+        }
+
+        score
+    }
+}
+
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnTable {
+    pub fn new() -> PawnTable {
+        PawnTable { entries: vec![PawnEntry::default(); TABLE_SIZE] }
+    }
+
+    pub fn probe(&mut self, params: &Params, board: &Board) -> PawnEntry {
+        let key = board.pawn_key();
+        let index = key as usize & (TABLE_SIZE - 1);
+
+        // a zeroed slot also matches the key of a board without pawns, which
+        // correctly evaluates to nothing
+        if self.entries[index].key != key {
+            self.entries[index] = PawnEntry::analyse(params, board, key);
+        }
+        self.entries[index]
+    }
+}
@@ -0,0 +1,797 @@
+use std::fs::File;
+use std::io::Read;
+
+use {Board, Color, Move, Piece, Square, EMPTY, FILES, RANKS};
+use book;
+use uci;
+
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    // SAN of the main line, without move numbers, comments or variations
+    pub moves: Vec<String>,
+    // the comments following each move of `moves`, empty when there are none
+    pub comments: Vec<String>,
+    // White's score, `None` for unfinished games
+    pub result: Option<f64>,
+}
+
+impl Game {
+    fn new() -> Game {
+        Game {
+            tags: vec![],
+            moves: vec![],
+            comments: vec![],
+            result: None,
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|&&(ref tag, _)| tag == name).map(|&(_, ref value)| value.as_str())
+    }
+
+    // The position the moves start from, given by the FEN tag when there is one
+    pub fn start(&self) -> Result<Replay, String> {
+        let mut replay = match self.tag("FEN") {
+            Some(fen) => Replay::from_fen(fen)?,
+            None => Replay::new(),
+        };
+        if self.tag("Variant").map_or(false, |variant| variant.to_lowercase().contains("960")) {
+            replay.chess960 = true;
+        }
+        Ok(replay)
+    }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let line = line.trim().trim_start_matches('[').trim_end_matches(']');
+    let space = line.find(' ')?;
+    let value = line[space..].trim().trim_matches('"').replace("\\\"", "\"");
+    Some((line[..space].to_string(), value))
+}
+
+fn parse_result(token: &str) -> Option<Option<f64>> {
+    match token {
+        "1-0" => Some(Some(1.0)),
+        "0-1" => Some(Some(0.0)),
+        "1/2-1/2" => Some(Some(0.5)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+pub fn parse_games(text: &str) -> Vec<Game> {
+    let mut games = vec![];
+    let mut game = Game::new();
+    // nesting of `(` variations and whether we are in a `{` comment, both can span
+    // lines. Comments of the main line are passed on as tokens starting with `{`.
+    let mut variation = 0;
+    let mut comment = false;
+    let mut remark = String::new();
+
+    for line in text.lines() {
+        if !comment && variation == 0 && line.starts_with('[') {
+            if !game.moves.is_empty() {
+                games.push(game);
+                game = Game::new();
+            }
+            if let Some(tag) = parse_tag(line) {
+                game.tags.push(tag);
+            }
+            continue;
+        }
+        if line.starts_with('%') {
+            continue;
+        }
+
+        let mut tokens = vec![];
+        let mut token = String::new();
+        for c in line.chars() {
+            if comment {
+                comment = c != '}';
+                if comment {
+                    remark.push(c);
+                } else if variation == 0 {
+                    tokens.push(format!("{{{}", remark.trim()));
+                }
+                continue;
+            }
+            match c {
+                '{' | '(' | ')' | ';' => {
+                    tokens.push(token.clone());
+                    token.clear();
+                    match c {
+                        '{' => {
+                            comment = true;
+                            remark.clear();
+                        }
+                        '(' => variation += 1,
+                        ')' => variation -= 1,
+                        _ => break,
+                    }
+                }
+                c if c.is_whitespace() => {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+                c if variation == 0 => token.push(c),
+                _ => (),
+            }
+        }
+        tokens.push(token);
+        if comment {
+            remark.push(' ');
+        }
+
+        for token in tokens.iter().filter(|token| !token.is_empty() && !token.starts_with('$')) {
+            // a comment before the first move is about the game, not kept
+            if let Some(remark) = token.strip_prefix('{') {
+                if let Some(last) = game.comments.last_mut() {
+                    if !last.is_empty() {
+                        last.push(' ');
+                    }
+                    last.push_str(remark);
+                }
+                continue;
+            }
+            if let Some(result) = parse_result(token) {
+                game.result = result;
+                games.push(game);
+                game = Game::new();
+                continue;
+            }
+            // move numbers may be glued to the move, `0-0` castling is not one
+            let san = if token.starts_with("0-0") {
+                token.as_str()
+            } else {
+                token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+            };
+            if !san.is_empty() {
+                game.moves.push(san.to_string());
+                game.comments.push(String::new());
+            }
+        }
+    }
+
+    if !game.moves.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+pub fn read_games(path: &str) -> Result<Vec<Game>, String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes).map_err(|e| format!("{}: {}", path, e))?;
+    // older collections are often Latin-1, the SAN itself is plain ASCII
+    Ok(parse_games(&String::from_utf8_lossy(&bytes)))
+}
+
+fn piece_from_char(c: char) -> Option<Piece> {
+    match c {
+        'K' => Some(Piece::King),
+        'Q' => Some(Piece::Queen),
+        'R' => Some(Piece::Rook),
+        'B' => Some(Piece::Bishop),
+        'N' => Some(Piece::Knight),
+        _ => None,
+    }
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::King => 'K',
+        Piece::Queen => 'Q',
+        Piece::Rook => 'R',
+        Piece::Bishop => 'B',
+        Piece::Knight => 'N',
+        _ => 'P',
+    }
+}
+
+fn parse_square(file: char, rank: char) -> Option<Square> {
+    match rank.to_digit(10) {
+        Some(rank) if FILES.contains(&file) && RANKS.contains(&(rank as u8)) => {
+            Some(Square::new(file, rank as u8))
+        }
+        _ => None,
+    }
+}
+
+// A game being replayed, with the castling, en passant and promotion rules that
+// `Board::exec_move` leaves out
+#[derive(Clone, Copy, Debug)]
+pub struct Replay {
+    pub board: Board,
+    pub turn: Color,
+    // square a pawn can be taken on en passant
+    pub en_passant: Option<Square>,
+    // by color, the files of the rooks still able to castle, queen side first
+    pub castling: [[Option<char>; 2]; 2],
+    // UCI then writes castling as the king taking its own rook
+    pub chess960: bool,
+}
+
+fn home_rank(color: Color) -> u8 {
+    match color {
+        Color::White => 1,
+        Color::Black => 8,
+    }
+}
+
+// Where the king and rook end up, by side
+fn castled_files(king_side: bool) -> (char, char) {
+    if king_side { ('g', 'f') } else { ('c', 'd') }
+}
+
+fn files_between(a: char, b: char) -> Vec<char> {
+    let (low, high) = (a.min(b) as u8, a.max(b) as u8);
+    (low..high + 1).map(|c| c as char).collect()
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay::from_board(Board::new(), Color::White)
+    }
+
+    pub fn chess960(index: usize) -> Replay {
+        let mut replay = Replay::from_board(Board::chess960(index), Color::White);
+        replay.chess960 = true;
+        replay
+    }
+
+    // Castling rights follow from the pieces: a king on its first rank can
+    // castle with the outermost rook on either side of it
+    pub fn from_board(board: Board, turn: Color) -> Replay {
+        let mut replay = Replay {
+            board: board,
+            turn: turn,
+            en_passant: None,
+            castling: [[None; 2]; 2],
+            chess960: false,
+        };
+        for &color in &[Color::Black, Color::White] {
+            for &king_side in &[false, true] {
+                replay.castling[color as usize][king_side as usize] =
+                    replay.outermost_rook(color, king_side);
+            }
+        }
+        replay
+    }
+
+    // A FEN record whose castling field is either standard, X-FEN or
+    // Shredder-FEN
+    pub fn from_fen(fen: &str) -> Result<Replay, String> {
+        let (board, turn) = Board::from_fen(fen)?;
+        let fields = fen.split_whitespace().collect::<Vec<&str>>();
+        let mut replay = Replay::from_board(board, turn);
+        replay.en_passant = fields.get(3).and_then(|field| uci::parse_square(field));
+        if let Some(field) = fields.get(2) {
+            replay.castling = [[None; 2]; 2];
+            for c in field.chars().filter(|&c| c != '-') {
+                let color = if c.is_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let king = match replay.home_king(color) {
+                    Some(king) => king,
+                    None => return Err(format!("invalid castling field '{}'", field)),
+                };
+                let (king_side, rook) = match c.to_ascii_lowercase() {
+                    'k' => (true, replay.outermost_rook(color, true)),
+                    'q' => (false, replay.outermost_rook(color, false)),
+                    file if FILES.contains(&file) => (file > king.file, Some(file)),
+                    _ => return Err(format!("invalid castling field '{}'", field)),
+                };
+                if rook.map(|file| board.get(&Square::new(file, king.rank))) !=
+                   Some((color, Piece::Rook)) {
+                    return Err(format!("no rook to castle with for '{}'", c));
+                }
+                replay.castling[color as usize][king_side as usize] = rook;
+            }
+            replay.chess960 = replay.castling.iter().any(|rooks| {
+                rooks.iter().any(|&rook| rook.map_or(false, |file| file != 'a' && file != 'h'))
+            });
+            for &color in &[Color::Black, Color::White] {
+                let rooks = replay.castling[color as usize];
+                if rooks.iter().any(|rook| rook.is_some()) &&
+                   replay.home_king(color).map(|king| king.file) != Some('e') {
+                    replay.chess960 = true;
+                }
+            }
+        }
+        Ok(replay)
+    }
+
+    // The castling field is written in X-FEN, using the rook's file only when
+    // another rook stands further out, or always in Shredder-FEN
+    pub fn to_fen(self, shredder: bool) -> String {
+        let board_fen = self.board.to_fen(self.turn);
+        let fields = board_fen.split_whitespace().take(2).collect::<Vec<&str>>();
+        let mut castling = String::new();
+        for &color in &[Color::White, Color::Black] {
+            for &king_side in &[true, false] {
+                let file = match self.castling[color as usize][king_side as usize] {
+                    Some(file) => file,
+                    None => continue,
+                };
+                let c = if shredder || self.outermost_rook(color, king_side) != Some(file) {
+                    file
+                } else if king_side {
+                    'k'
+                } else {
+                    'q'
+                };
+                match color {
+                    Color::White => castling.extend(c.to_uppercase()),
+                    Color::Black => castling.push(c),
+                }
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        format!("{} {} {} {} 0 1",
+                fields[0],
+                fields[1],
+                castling,
+                self.en_passant.map_or("-".to_string(), |square| square.to_string()))
+    }
+
+    // Polyglot key from the rights actually left, rather than the ones
+    // `Board::polyglot_key` guesses from the pieces
+    pub fn key(&self) -> u64 {
+        let right = |color: Color, king_side: bool| {
+            self.castling[color as usize][king_side as usize].is_some()
+        };
+        let castling = [right(Color::White, true),
+                        right(Color::White, false),
+                        right(Color::Black, true),
+                        right(Color::Black, false)];
+        self.board.polyglot_key_with(self.turn, castling, self.en_passant)
+    }
+
+    fn home_king(&self, color: Color) -> Option<Square> {
+        match self.board.king(color) {
+            Some(king) if king.rank == home_rank(color) => Some(king),
+            _ => None,
+        }
+    }
+
+    fn outermost_rook(&self, color: Color, king_side: bool) -> Option<char> {
+        let king = self.home_king(color)?;
+        let mut files = FILES.iter()
+            .filter(|&&file| (file > king.file) == king_side && file != king.file)
+            .filter(|&&file| self.board.get(&Square::new(file, king.rank)) == (color, Piece::Rook))
+            .cloned();
+        if king_side { files.next_back() } else { files.next() }
+    }
+
+    // The king and the rook it castles with
+    fn castling_squares(&self, king_side: bool) -> Option<(Square, Square)> {
+        let rook = match self.castling[self.turn as usize][king_side as usize] {
+            Some(file) => Square::new(file, home_rank(self.turn)),
+            None => return None,
+        };
+        match self.home_king(self.turn) {
+            Some(king) if self.board.get(&rook) == (self.turn, Piece::Rook) => Some((king, rook)),
+            _ => None,
+        }
+    }
+
+    // Every square the king and rook cross or land on must be empty, apart from
+    // the two of them
+    fn can_castle(&self, king_side: bool) -> bool {
+        let (king, rook) = match self.castling_squares(king_side) {
+            Some(squares) => squares,
+            None => return false,
+        };
+        let (king_to, rook_to) = castled_files(king_side);
+        files_between(king.file, king_to)
+            .into_iter()
+            .chain(files_between(rook.file, rook_to))
+            .map(|file| Square::new(file, king.rank))
+            .all(|square| {
+                square == king || square == rook || self.board.get(&square).1 == Piece::Empty
+            })
+    }
+
+    fn castle(&mut self, king_side: bool) -> Result<u16, String> {
+        if !self.can_castle(king_side) {
+            return Err("cannot castle".to_string());
+        }
+        let (king, rook) = self.castling_squares(king_side).expect("No castling squares");
+        let (king_to, rook_to) = castled_files(king_side);
+        self.board.set(king, EMPTY);
+        self.board.set(rook, EMPTY);
+        self.board.set(Square::new(king_to, king.rank), (self.turn, Piece::King));
+        self.board.set(Square::new(rook_to, king.rank), (self.turn, Piece::Rook));
+        self.castling[self.turn as usize] = [None; 2];
+        self.en_passant = None;
+        self.turn = self.turn.other();
+        // Polyglot writes castling as the king taking its own rook
+        Ok(book::encode_move(&king, &rook, None))
+    }
+
+    // The side a move castles to, written as the king taking its rook or, in
+    // standard chess, as the king moving two files to the g or c file
+    fn castles(&self, cmove: &Move) -> Option<bool> {
+        let (from, to) = *cmove;
+        if self.board.get(&from) != (self.turn, Piece::King) || from.rank != to.rank {
+            return None;
+        }
+        if self.board.get(&to) == (self.turn, Piece::Rook) {
+            let king_side = to.file > from.file;
+            return match self.castling_squares(king_side) {
+                Some((_, rook)) if rook == to => Some(king_side),
+                _ => None,
+            };
+        }
+        let king_side = match to.file {
+            'g' => true,
+            'c' => false,
+            _ => return None,
+        };
+        if !self.chess960 && (from.file as i32 - to.file as i32).abs() == 2 &&
+           self.castling_squares(king_side).is_some() {
+            Some(king_side)
+        } else {
+            None
+        }
+    }
+
+    // Castling as the king taking its rook, as `legal_moves` lists it
+    pub fn canonical(&self, cmove: Move) -> Move {
+        match self.castles(&cmove).and_then(|king_side| self.castling_squares(king_side)) {
+            Some(squares) => squares,
+            None => cmove,
+        }
+    }
+
+    // A move in UCI's notation
+    pub fn uci(&self, cmove: &Move, promotion: Option<Piece>) -> String {
+        let (from, mut to) = *cmove;
+        if let Some(king_side) = self.castles(cmove) {
+            if !self.chess960 {
+                to = Square::new(castled_files(king_side).0, from.rank);
+            }
+        }
+        let suffix = match promotion {
+            Some(Piece::Knight) => "n",
+            Some(Piece::Bishop) => "b",
+            Some(Piece::Rook) => "r",
+            Some(Piece::Queen) => "q",
+            _ => "",
+        };
+        format!("{}{}{}", from, to, suffix)
+    }
+
+    pub fn is_en_passant(&self, cmove: &Move) -> bool {
+        let (from, to) = *cmove;
+        self.board.get(&from).1 == Piece::Pawn && from.file != to.file &&
+        Some(to) == self.en_passant
+    }
+
+    // The piece a pawn reaching its last rank turns into, a queen unless told otherwise
+    pub fn promotion(&self, cmove: &Move, promotion: Option<Piece>) -> Option<Piece> {
+        match (self.board.get(&cmove.0).1, cmove.1.rank) {
+            (Piece::Pawn, 1) | (Piece::Pawn, 8) => Some(promotion.unwrap_or(Piece::Queen)),
+            _ => None,
+        }
+    }
+
+    // Moves that do not leave the king in check, castling as the king taking
+    // its rook
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let turn = self.turn;
+        let mut moves = self.board.legal_moves(turn);
+        if let Some(to) = self.en_passant {
+            let rank = match turn {
+                Color::White => to.rank - 1,
+                Color::Black => to.rank + 1,
+            };
+            for from in [to.left(), to.right()].iter().filter_map(|s| *s) {
+                let from = Square::new(from.file, rank);
+                if self.board.get(&from) == (turn, Piece::Pawn) {
+                    moves.push((from, to));
+                }
+            }
+        }
+        for &king_side in &[true, false] {
+            if !self.can_castle(king_side) || self.board.in_check(turn) {
+                continue;
+            }
+            let (king, rook) = self.castling_squares(king_side).expect("No castling squares");
+            let (king_to, _) = castled_files(king_side);
+            let attacked = files_between(king.file, king_to)
+                .into_iter()
+                .any(|file| self.board.is_attacked(&Square::new(file, king.rank), turn.other()));
+            if !attacked {
+                moves.push((king, rook));
+            }
+        }
+
+        moves.into_iter()
+            .filter(|cmove| {
+                let mut after = *self;
+                after.play(*cmove, None).is_ok() && !after.board.in_check(turn)
+            })
+            .collect()
+    }
+
+    // Plays a move given by its squares, returning it in Polyglot's encoding. A
+    // pawn reaching the last rank becomes a queen unless told otherwise.
+    pub fn play(&mut self, cmove: Move, promotion: Option<Piece>) -> Result<u16, String> {
+        let (from, to) = cmove;
+        let turn = self.turn;
+        let (color, piece) = self.board.get(&from);
+        if color != turn || piece == Piece::Empty {
+            return Err(format!("no piece to move on {}", from));
+        }
+        if let Some(king_side) = self.castles(&cmove) {
+            return self.castle(king_side);
+        }
+        if piece == Piece::King {
+            self.castling[turn as usize] = [None; 2];
+        }
+        // a rook leaving or taken on its square loses its castling
+        for &color in &[Color::Black, Color::White] {
+            let rank = home_rank(color);
+            for rook in self.castling[color as usize].iter_mut() {
+                let square = rook.map(|file| Square::new(file, rank));
+                if square == Some(from) || square == Some(to) {
+                    *rook = None;
+                }
+            }
+        }
+
+        let mut board = self.board.exec_move(&from, &to);
+        if self.is_en_passant(&cmove) {
+            board.set(Square::new(to.file, from.rank), EMPTY);
+        }
+        let promotion = self.promotion(&cmove, promotion);
+        if let Some(promoted) = promotion {
+            board.set(to, (turn, promoted));
+        }
+
+        let double_push = piece == Piece::Pawn && (from.rank as i32 - to.rank as i32).abs() == 2;
+        self.en_passant = if double_push {
+            Some(Square::new(from.file, (from.rank + to.rank) / 2))
+        } else {
+            None
+        };
+        self.board = board;
+        self.turn = turn.other();
+        Ok(book::encode_move(&from, &to, promotion))
+    }
+
+    // The move a SAN string stands for and the piece it promotes to
+    pub fn parse_san(&self, san: &str) -> Result<(Move, Option<Piece>), String> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        match san {
+            "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+                let king_side = san.len() == 3;
+                if !self.can_castle(king_side) {
+                    return Err("cannot castle".to_string());
+                }
+                return Ok((self.castling_squares(king_side).expect("No castling squares"), None));
+            }
+            _ => (),
+        }
+
+        let (body, promotion) = match san.find('=') {
+            Some(index) => {
+                (&san[..index], san[index + 1..].chars().next().and_then(piece_from_char))
+            }
+            None => (san, None),
+        };
+        let mut chars = body.chars().filter(|&c| c != 'x' && c != ':').collect::<Vec<char>>();
+        let piece = match chars.first().cloned().and_then(piece_from_char) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            }
+            None => Piece::Pawn,
+        };
+        if chars.len() < 2 {
+            return Err(format!("invalid move '{}'", san));
+        }
+        let to = match parse_square(chars[chars.len() - 2], chars[chars.len() - 1]) {
+            Some(square) => square,
+            None => return Err(format!("invalid move '{}'", san)),
+        };
+        let hints = &chars[..chars.len() - 2];
+        let matches = |from: &Square| {
+            hints.iter()
+                .all(|&hint| hint == from.file || Some(from.rank as u32) == hint.to_digit(10))
+        };
+
+        let turn = self.turn;
+        let board = self.board;
+        let mut candidates = board.legal_moves(turn)
+            .into_iter()
+            .filter(|&(from, dest)| {
+                dest == to && board.get(&from) == (turn, piece) && matches(&from)
+            })
+            .collect::<Vec<(Square, Square)>>();
+        if piece == Piece::Pawn && Some(to) == self.en_passant {
+            let rank = match turn {
+                Color::White => to.rank - 1,
+                Color::Black => to.rank + 1,
+            };
+            for from in [to.left(), to.right()].iter().filter_map(|s| *s) {
+                let from = Square::new(from.file, rank);
+                if self.board.get(&from) == (turn, Piece::Pawn) && matches(&from) {
+                    candidates.push((from, to));
+                }
+            }
+        }
+        // SAN does not disambiguate against pinned pieces
+        if candidates.len() > 1 {
+            candidates.retain(|&(from, dest)| !board.exec_move(&from, &dest).in_check(turn));
+        }
+        match candidates.len() {
+            1 => Ok((candidates[0], promotion)),
+            0 => Err(format!("illegal move '{}'", san)),
+            _ => Err(format!("ambiguous move '{}'", san)),
+        }
+    }
+
+    // Plays a move given in SAN, returning it in Polyglot's encoding
+    pub fn play_san(&mut self, san: &str) -> Result<u16, String> {
+        let (cmove, promotion) = self.parse_san(san)?;
+        self.play(cmove, promotion)
+    }
+
+    // The SAN of a legal move, with its check or mate suffix
+    pub fn san(&self, cmove: &Move, promotion: Option<Piece>) -> String {
+        let (from, to) = *cmove;
+        let piece = self.board.get(&from).1;
+        let mut san = match self.castles(cmove) {
+            Some(true) => "O-O".to_string(),
+            Some(false) => "O-O-O".to_string(),
+            None => {
+                let capture = self.board.get(&to).1 != Piece::Empty || self.is_en_passant(cmove);
+                let mut san = String::new();
+                if piece == Piece::Pawn {
+                    if capture {
+                        san.push(from.file);
+                    }
+                } else {
+                    san.push(piece_char(piece));
+                    let others = self.legal_moves()
+                        .into_iter()
+                        .filter(|&(other, dest)| {
+                            dest == to && other != from && self.board.get(&other).1 == piece
+                        })
+                        .map(|(other, _)| other)
+                        .collect::<Vec<Square>>();
+                    if !others.is_empty() {
+                        if others.iter().all(|other| other.file != from.file) {
+                            san.push(from.file);
+                        } else if others.iter().all(|other| other.rank != from.rank) {
+                            san.push_str(&from.rank.to_string());
+                        } else {
+                            san.push_str(&from.to_string());
+                        }
+                    }
+                }
+                if capture {
+                    san.push('x');
+                }
+                san.push_str(&to.to_string());
+                if piece == Piece::Pawn && (to.rank == 1 || to.rank == 8) {
+                    san.push('=');
+                    san.push(piece_char(promotion.unwrap_or(Piece::Queen)));
+                }
+                san
+            }
+        };
+
+        let mut after = *self;
+        if after.play(*cmove, promotion).is_ok() && after.board.in_check(after.turn) {
+            san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        san
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Replay;
+    use {Board, Color, Piece, Square};
+
+    fn key_after(moves: &[&str]) -> u64 {
+        let mut replay = Replay::new();
+        for san in moves {
+            replay.play_san(san).unwrap();
+        }
+        replay.key()
+    }
+
+    // The en passant square is keyed only while a pawn can take on it, and the
+    // capture removes the pawn that passed
+    #[test]
+    fn key_after_en_passant() {
+        let fen = "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq {} 0 3";
+        let replay = Replay::from_fen(&fen.replace("{}", "c3")).unwrap();
+        assert_eq!(key_after(&["a4", "b5", "h4", "b4", "c4"]), replay.key());
+        assert!(replay.key() != Replay::from_fen(&fen.replace("{}", "-")).unwrap().key());
+
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(Replay::from_fen(fen).unwrap().key(),
+                   Replay::from_fen(&fen.replace("e3", "-")).unwrap().key());
+
+        // the last Polyglot example: the rook leaving a1 also costs a castling right
+        assert_eq!(key_after(&["a4", "b5", "h4", "b4", "c4", "bxc3", "Ra3"]),
+                   0x5c3f9b829b279560);
+    }
+
+    #[test]
+    fn key_uses_rights_left() {
+        let mut replay = Replay::new();
+        for san in &["e4", "e5", "Ke2", "Ke7", "Ke1", "Ke8"] {
+            replay.play_san(san).unwrap();
+        }
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w - - 0 1";
+        assert_eq!(replay.key(), Replay::from_fen(fen).unwrap().key());
+        assert!(replay.key() != replay.board.polyglot_key(replay.turn, replay.en_passant));
+    }
+
+    #[test]
+    fn chess960_518_is_the_standard_position() {
+        assert!(Board::chess960(518).squares == Board::new().squares);
+        let replay = Replay::chess960(518);
+        assert_eq!(replay.to_fen(false),
+                   "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(replay.to_fen(true),
+                   "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1");
+        assert_eq!(replay.key(), Replay::new().key());
+    }
+
+    #[test]
+    fn castling_with_the_king_on_its_target_square() {
+        let fen = "rnbqrbkr/pppppppp/8/8/8/8/PPPPPPPP/RNBQR1KR w HEhe - 0 1";
+        let mut replay = Replay::from_fen(fen).unwrap();
+        assert!(replay.chess960);
+        let (cmove, _) = replay.parse_san("O-O").unwrap();
+        assert_eq!(replay.uci(&cmove, None), "g1h1");
+        replay.play(cmove, None).unwrap();
+        assert!(replay.board.get(&Square::new('g', 1)) == (Color::White, Piece::King));
+        assert!(replay.board.get(&Square::new('f', 1)) == (Color::White, Piece::Rook));
+        assert!(replay.board.get(&Square::new('h', 1)).1 == Piece::Empty);
+        assert_eq!(replay.castling[Color::White as usize], [None, None]);
+    }
+
+    #[test]
+    fn castling_with_the_rook_on_its_target_square() {
+        let mut replay = Replay::from_fen("4k3/8/8/8/8/8/8/3RK2R w HD - 0 1").unwrap();
+        assert!(replay.chess960);
+        let (cmove, _) = replay.parse_san("O-O-O").unwrap();
+        assert_eq!(replay.uci(&cmove, None), "e1d1");
+        replay.play(cmove, None).unwrap();
+        assert!(replay.board.get(&Square::new('c', 1)) == (Color::White, Piece::King));
+        assert!(replay.board.get(&Square::new('d', 1)) == (Color::White, Piece::Rook));
+        assert!(replay.board.get(&Square::new('e', 1)).1 == Piece::Empty);
+        assert!(replay.board.get(&Square::new('h', 1)) == (Color::White, Piece::Rook));
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for &index in &[0, 1, 341, 518, 959] {
+            let replay = Replay::chess960(index);
+            for &shredder in &[false, true] {
+                let fen = replay.to_fen(shredder);
+                assert_eq!(Replay::from_fen(&fen).unwrap().to_fen(shredder), fen);
+            }
+        }
+
+        // X-FEN names a rook by its file only when another stands further out
+        for fen in &["r3k1rr/8/8/8/8/8/8/RR2K3 w Bg - 0 1",
+                     "rn2k1r1/ppp1pppp/8/8/8/8/PPP1PPPP/1R2K1NR b Kq - 0 1"] {
+            assert_eq!(Replay::from_fen(fen).unwrap().to_fen(false), *fen);
+        }
+        let shredder = Replay::from_fen("r3k1rr/8/8/8/8/8/8/RR2K3 w Bg - 0 1").unwrap();
+        assert_eq!(shredder.to_fen(true), "r3k1rr/8/8/8/8/8/8/RR2K3 w Bg - 0 1");
+    }
+}
@@ -0,0 +1,584 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use time;
+
+use {move_string, Board, Color, Move};
+use eval::{Evaluator, MATE};
+use movepick::{is_capture, CounterMoves, History, Killers, MovePicker};
+use params::Params;
+use tt::{Bound, TranspositionTable};
+
+const TT_SIZE: usize = 1 << 20;
+const INFINITY: i32 = MATE + 1;
+pub const MAX_PLY: usize = 128;
+pub const MAX_DEPTH: usize = 64;
+// How often a thread publishes its node count and checks for a stop request
+const NODE_BATCH: usize = 1024;
+
+const ASPIRATION_DEPTH: usize = 4;
+const ASPIRATION_WINDOW: i32 = 40;
+
+const REVERSE_FUTILITY_DEPTH: usize = 3;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+// Indexed by remaining depth
+const FUTILITY_MARGINS: [i32; 4] = [0, 200, 350, 500];
+
+// Each selectivity technique can be switched off to measure what it is worth
+#[derive(Clone, Copy, Debug)]
+pub struct SearchOptions {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    pub check_extensions: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            check_extensions: true,
+        }
+    }
+}
+
+impl SearchOptions {
+    pub fn disable(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "null_move" => self.null_move = false,
+            "lmr" => self.late_move_reductions = false,
+            "reverse_futility" => self.reverse_futility = false,
+            "futility" => self.futility = false,
+            "check_extensions" => self.check_extensions = false,
+            _ => return Err(format!("unknown search option '{}'", name)),
+        }
+        Ok(())
+    }
+}
+
+// log(depth) * log(move number) / 2, the usual shape for late move reductions
+fn reduction_table() -> Vec<Vec<usize>> {
+    (0..MAX_DEPTH)
+        .map(|depth| {
+            (0..64)
+                .map(|count| {
+                    if depth == 0 || count == 0 {
+                        0
+                    } else {
+                        ((depth as f64).ln() * (count as f64).ln() / 2.0) as usize
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Mate scores are stored relative to the node so they stay valid when the
+// same position is reached at a different ply
+fn to_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score < -MATE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn from_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score < -MATE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// One completed iteration, printed as a UCI `info` line
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: usize,
+    // rank of this line among the root moves, starting at 1
+    pub multipv: usize,
+    pub score: i32,
+    pub nodes: usize,
+    pub time_ms: u64,
+    pub pv: Vec<Move>,
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "info depth {} multipv {} score ", self.depth, self.multipv)?;
+        if self.score.abs() > MATE - MAX_PLY as i32 {
+            // the king is taken one ply after the mating move
+            let plies = MATE - self.score.abs() - 1;
+            if self.score > 0 {
+                write!(f, "mate {}", (plies + 1) / 2)?;
+            } else {
+                write!(f, "mate -{}", plies / 2)?;
+            }
+        } else {
+            write!(f, "cp {}", self.score)?;
+        }
+
+        let nps = self.nodes as u64 * 1000 / self.time_ms.max(1);
+        write!(f,
+               " nodes {} nps {} time {} pv",
+               self.nodes,
+               nps,
+               self.time_ms)?;
+        for cmove in &self.pv {
+            write!(f, " {}", move_string(cmove))?;
+        }
+        Ok(())
+    }
+}
+
+// State every search thread sees: the hash table persists between moves, the
+// stop flag and node count are reset for each one. While `pondering` is set the
+// search ignores its depth limit, it is cleared on a ponder hit. `timed` does
+// the same for a search a timer stops.
+pub struct Shared {
+    pub tt: TranspositionTable,
+    pub stop: AtomicBool,
+    pub pondering: AtomicBool,
+    pub timed: AtomicBool,
+    pub nodes: AtomicUsize,
+}
+
+impl Shared {
+    pub fn new() -> Shared {
+        Shared {
+            tt: TranspositionTable::new(TT_SIZE),
+            stop: AtomicBool::new(false),
+            pondering: AtomicBool::new(false),
+            timed: AtomicBool::new(false),
+            nodes: AtomicUsize::new(0),
+        }
+    }
+}
+
+pub struct Search {
+    id: usize,
+    options: SearchOptions,
+    reductions: Vec<Vec<usize>>,
+    evaluator: Evaluator,
+    shared: Arc<Shared>,
+    stopped: bool,
+    history: History,
+    killers: Killers,
+    countermoves: CounterMoves,
+    // triangular array: pv[ply] holds the best line found from that ply
+    pv: Vec<Vec<Move>>,
+    // root moves already reported as a better line in this iteration
+    excluded: Vec<Move>,
+    // best line of each completed iteration of the last search
+    iterations: Vec<SearchInfo>,
+    quiet: bool,
+    nodes: usize,
+}
+
+impl Search {
+    pub fn new(id: usize, params: Params, options: SearchOptions, shared: Arc<Shared>) -> Search {
+        Search {
+            id: id,
+            options: options,
+            reductions: reduction_table(),
+            evaluator: Evaluator::with_params(params),
+            shared: shared,
+            stopped: false,
+            history: History::new(),
+            killers: Killers::new(MAX_PLY),
+            countermoves: CounterMoves::new(),
+            pv: vec![vec![]; MAX_PLY + 1],
+            excluded: vec![],
+            iterations: vec![],
+            quiet: false,
+            nodes: 0,
+        }
+    }
+
+    // Move ordering statistics are otherwise kept from one search to the next
+    pub fn clear(&mut self) {
+        self.history = History::new();
+        self.countermoves = CounterMoves::new();
+    }
+
+    pub fn iterations(&self) -> &[SearchInfo] {
+        &self.iterations
+    }
+
+    // Stops the main thread printing an info line for each iteration
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    // Iterative deepening up to `depth`, or past it for as long as the shared
+    // `pondering` or `timed` flag is set. Each iteration searches the root `lines` times,
+    // leaving out the moves of the lines already found. Helper threads start on
+    // alternating depths so they spread over different iterations, and only the
+    // main thread reports its progress. Returns the lines of the last full
+    // iteration, best first.
+    pub fn iterate(&mut self,
+                   board: &Board,
+                   turn: Color,
+                   depth: usize,
+                   lines: usize)
+                   -> Vec<SearchInfo> {
+        let start = time::precise_time_ns();
+        self.nodes = 0;
+        self.stopped = false;
+        self.killers = Killers::new(MAX_PLY);
+        self.iterations.clear();
+
+        let mut result: Vec<SearchInfo> = vec![];
+        for current in (1 + self.id % 2)..MAX_DEPTH {
+            if current > depth && !self.shared.pondering.load(Ordering::Relaxed) &&
+               !self.shared.timed.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let mut iteration = vec![];
+            self.excluded.clear();
+            for index in 0..lines.max(1) {
+                let previous = result.get(index).map_or(0, |line| line.score);
+                let score = self.aspiration(board, turn, current, previous);
+                if self.stopped || self.pv[0].is_empty() {
+                    break;
+                }
+                self.excluded.push(self.pv[0][0]);
+                iteration.push(SearchInfo {
+                    depth: current,
+                    multipv: index + 1,
+                    score: score,
+                    nodes: 0,
+                    time_ms: 0,
+                    pv: self.pv[0].clone(),
+                });
+            }
+            if self.stopped || iteration.is_empty() {
+                break;
+            }
+
+            iteration.sort_by_key(|line| Reverse(line.score));
+            let nodes = self.shared.nodes.load(Ordering::Relaxed) + self.nodes % NODE_BATCH;
+            let time_ms = (time::precise_time_ns() - start) / 1000000;
+            for (index, line) in iteration.iter_mut().enumerate() {
+                line.multipv = index + 1;
+                line.nodes = nodes;
+                line.time_ms = time_ms;
+                if self.id == 0 && !self.quiet {
+                    println!("{}", line);
+                }
+            }
+            self.iterations.push(iteration[0].clone());
+            result = iteration;
+        }
+        self.excluded.clear();
+        result
+    }
+
+    // Returns true once the search has been asked to stop
+    fn count_node(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes % NODE_BATCH == 0 {
+            self.shared.nodes.fetch_add(NODE_BATCH, Ordering::Relaxed);
+            if self.shared.stop.load(Ordering::Relaxed) {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    // Searches a narrow window around the previous iteration's score, widening
+    // it on the side that failed until the score lands inside
+    fn aspiration(&mut self, board: &Board, turn: Color, depth: usize, previous: i32) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = if depth >= ASPIRATION_DEPTH {
+            ((previous - delta).max(-INFINITY), (previous + delta).min(INFINITY))
+        } else {
+            (-INFINITY, INFINITY)
+        };
+
+        loop {
+            let score = self.alpha_beta(board, turn, depth, 0, alpha, beta, None);
+            if score <= alpha && alpha > -INFINITY {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+        }
+    }
+
+    // Side to move's point of view
+    fn evaluate(&mut self, board: &Board, turn: Color) -> i32 {
+        let score = self.evaluator.evaluate(board, turn);
+        match turn {
+            Color::Black => -score,
+            Color::White => score,
+        }
+    }
+
+    // `previous` is None at the root and right after a null move, which is also
+    // what keeps two null moves from being played in a row
+    #[allow(clippy::too_many_arguments)]
+    fn alpha_beta(&mut self,
+                  board: &Board,
+                  turn: Color,
+                  mut depth: usize,
+                  ply: usize,
+                  mut alpha: i32,
+                  beta: i32,
+                  previous: Option<Move>)
+                  -> i32 {
+        self.pv[ply.min(MAX_PLY)].clear();
+        if self.count_node() {
+            return 0;
+        }
+
+        // moves may leave the king en prise, so losing it is how the game ends
+        if board.king(turn).is_none() {
+            return -MATE + ply as i32;
+        }
+
+        let pv_node = beta - alpha > 1;
+        let in_check = board.in_check(turn);
+        if in_check && self.options.check_extensions && ply < MAX_PLY / 2 {
+            depth += 1;
+        }
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(board, turn, ply, alpha, beta);
+        }
+        depth = depth.min(MAX_DEPTH - 1);
+
+        let key = board.hash(turn);
+        let mut hash_move = None;
+        if let Some(entry) = self.shared.tt.probe(key) {
+            hash_move = entry.best_move;
+            if !pv_node && entry.depth >= depth {
+                let score = from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let static_eval = if in_check || ply == 0 {
+            None
+        } else {
+            Some(self.evaluate(board, turn))
+        };
+
+        if let Some(eval) = static_eval {
+            if self.options.reverse_futility && !pv_node && depth <= REVERSE_FUTILITY_DEPTH &&
+               eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta {
+                return eval;
+            }
+
+            // passing is only a safe lower bound when the side to move has pieces
+            // to shuffle, pawn endings are where zugzwang lives
+            if self.options.null_move && !pv_node && depth >= 3 && previous.is_some() &&
+               eval >= beta && board.has_non_pawn_material(turn) {
+                let reduction = 3 + depth / 4;
+                let score = -self.alpha_beta(board,
+                                             turn.other(),
+                                             depth.saturating_sub(reduction),
+                                             ply + 1,
+                                             -beta,
+                                             -beta + 1,
+                                             None);
+                if score >= beta {
+                    // at high depth, confirm with a reduced search that cannot pass
+                    if depth < 8 {
+                        return beta;
+                    }
+                    let verified = self.alpha_beta(board,
+                                                   turn,
+                                                   depth.saturating_sub(reduction),
+                                                   ply,
+                                                   beta - 1,
+                                                   beta,
+                                                   None);
+                    if verified >= beta {
+                        return beta;
+                    }
+                }
+            }
+        }
+
+        let futile = match static_eval {
+            Some(eval) => {
+                self.options.futility && depth < FUTILITY_MARGINS.len() &&
+                eval + FUTILITY_MARGINS[depth] <= alpha
+            }
+            None => false,
+        };
+
+        let picker = MovePicker::new(board,
+                                     turn,
+                                     hash_move,
+                                     self.killers.get(ply),
+                                     self.countermoves.get(previous),
+                                     &self.history,
+                                     self.evaluator.params());
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut quiets = vec![];
+
+        let excluded = if ply == 0 {
+            self.excluded.clone()
+        } else {
+            vec![]
+        };
+        for (count, cmove) in picker.filter(|cmove| !excluded.contains(cmove)).enumerate() {
+            let capture = is_capture(board, &cmove);
+            if futile && !capture && best_move.is_some() {
+                continue;
+            }
+
+            let child = board.exec_move(&cmove.0, &cmove.1);
+            let reduction = if self.options.late_move_reductions && depth >= 3 && count >= 3 &&
+                               !capture && !in_check {
+                self.reductions[depth][count.min(63)].min(depth - 2)
+            } else {
+                0
+            };
+
+            // principal variation search: only the first move gets the full window,
+            // the rest are expected to fail low and are re-searched if they do not
+            let mut score;
+            if count == 0 {
+                score = -self.alpha_beta(&child,
+                                         turn.other(),
+                                         depth - 1,
+                                         ply + 1,
+                                         -beta,
+                                         -alpha,
+                                         Some(cmove));
+            } else {
+                score = -self.alpha_beta(&child,
+                                         turn.other(),
+                                         depth - 1 - reduction,
+                                         ply + 1,
+                                         -alpha - 1,
+                                         -alpha,
+                                         Some(cmove));
+                if reduction > 0 && score > alpha {
+                    score = -self.alpha_beta(&child,
+                                             turn.other(),
+                                             depth - 1,
+                                             ply + 1,
+                                             -alpha - 1,
+                                             -alpha,
+                                             Some(cmove));
+                }
+                if score > alpha && score < beta {
+                    score = -self.alpha_beta(&child,
+                                             turn.other(),
+                                             depth - 1,
+                                             ply + 1,
+                                             -beta,
+                                             -alpha,
+                                             Some(cmove));
+                }
+            }
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(cmove);
+            }
+            if score > alpha {
+                alpha = score;
+                let mut line = vec![cmove];
+                line.extend(self.pv[ply + 1].iter().cloned());
+                self.pv[ply] = line;
+            }
+
+            if alpha >= beta {
+                if !capture {
+                    self.killers.store(ply, cmove);
+                    self.countermoves.store(previous, cmove);
+                    self.history.update(turn, &cmove, &quiets, depth);
+                }
+                break;
+            }
+            if !capture {
+                quiets.push(cmove);
+            }
+        }
+
+        if best_move.is_none() {
+            return 0;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // a root with moves left out did not see the whole position
+        if excluded.is_empty() {
+            self.shared.tt.store(key, best_move, depth, to_tt(best_score, ply), bound);
+        }
+
+        best_score
+    }
+
+    fn quiescence(&mut self,
+                  board: &Board,
+                  turn: Color,
+                  ply: usize,
+                  mut alpha: i32,
+                  beta: i32)
+                  -> i32 {
+        self.pv[ply.min(MAX_PLY)].clear();
+        if self.count_node() {
+            return 0;
+        }
+
+        if board.king(turn).is_none() {
+            return -MATE + ply as i32;
+        }
+
+        let stand_pat = self.evaluate(board, turn);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        for cmove in MovePicker::captures(board, turn, self.evaluator.params()) {
+            let child = board.exec_move(&cmove.0, &cmove.1);
+            let score = -self.quiescence(&child, turn.other(), ply + 1, -beta, -alpha);
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        alpha
+    }
+}
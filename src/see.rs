@@ -0,0 +1,122 @@
+use {Board, Color, Move, Piece, Square, EMPTY};
+use params::Params;
+
+// Losing the king loses the game, so it is worth more than any exchange
+pub const KING_VALUE: i32 = 10000;
+
+fn see_value(params: &Params, piece: Piece) -> i32 {
+    match piece {
+        Piece::King => KING_VALUE,
+        _ => params.value(piece),
+    }
+}
+
+impl Board {
+    fn least_valuable_attacker(&self,
+                               params: &Params,
+                               target: &Square,
+                               color: Color)
+                               -> Option<Square> {
+        let mut best: Option<(Square, i32)> = None;
+        for (i, col) in self.squares.iter().enumerate() {
+            for (j, &(c, piece)) in col.iter().enumerate() {
+                if c != color || piece == Piece::Empty {
+                    continue;
+                }
+                let square = Square::from_indexes(i, j);
+                if best.map_or(false, |(_, value)| value <= see_value(params, piece)) {
+                    continue;
+                }
+                if self.attacks(&square).contains(target) {
+                    best = Some((square, see_value(params, piece)));
+                }
+            }
+        }
+        best.map(|(square, _)| square)
+    }
+
+    // Material balance, in `Params::value` centipawns, of the capture sequence
+    // started by `cmove` when both sides always recapture with their least valuable
+    // attacker and may stop whenever continuing would lose material. Pieces are
+    // removed from the board as they capture, so sliders lined up behind them join in.
+    pub fn see(&self, cmove: &Move, params: &Params) -> i32 {
+        let (from, to) = *cmove;
+        let mut board = *self;
+        let (mut color, mut piece) = board.get(&from);
+        let mut gain = vec![see_value(params, board.get(&to).1)];
+        board.set(from, EMPTY);
+
+        loop {
+            color = color.other();
+            let attacker = match board.least_valuable_attacker(params, &to, color) {
+                Some(square) => square,
+                None => break,
+            };
+            let last = *gain.last().unwrap();
+            gain.push(see_value(params, piece) - last);
+            piece = board.get(&attacker).1;
+            board.set(attacker, EMPTY);
+        }
+
+        while gain.len() > 1 {
+            let last = gain.pop().unwrap();
+            let previous = gain.last_mut().unwrap();
+            *previous = -(-*previous).max(last);
+        }
+        gain[0]
+    }
+
+    // Pieces of `color` the opponent can win material from by capturing them
+    pub fn hanging(&self, color: Color, params: &Params) -> Vec<Square> {
+        self.legal_moves(color.other())
+            .into_iter()
+            .filter(|&(_, to)| self.get(&to).0 == color && self.get(&to).1 != Piece::Empty)
+            .filter(|cmove| self.see(cmove, params) > 0)
+            .map(|(_, to)| to)
+            .fold(vec![], |mut squares, square| {
+                if !squares.contains(&square) {
+                    squares.push(square);
+                }
+                squares
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Board, Square};
+    use params::Params;
+
+    fn see(fen: &str, from: &str, to: &str) -> i32 {
+        let (board, _) = Board::from_fen(fen).unwrap();
+        let square = |name: &str| {
+            let name = name.as_bytes();
+            Square::new(name[0] as char, name[1] - b'0')
+        };
+        board.see(&(square(from), square(to)), &Params::default())
+    }
+
+    #[test]
+    fn undefended_pawn() {
+        assert_eq!(see("4k3/8/8/4p3/8/8/8/4RK2 w - - 0 1", "e1", "e5"), 85);
+    }
+
+    #[test]
+    fn pawn_defended_by_a_pawn() {
+        assert_eq!(see("4k3/8/3p4/4p3/8/3N4/8/4K3 w - - 0 1", "d3", "e5"), 85 - 320);
+        assert_eq!(see("4k3/8/3p4/4p3/3P4/8/8/4K3 w - - 0 1", "d4", "e5"), 0);
+    }
+
+    #[test]
+    fn rooks_lined_up_behind_each_other() {
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4RK2 w - - 0 1", "e2", "e5"), 85);
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/5K2 w - - 0 1", "e2", "e5"), 85 - 480);
+    }
+
+    #[test]
+    fn stops_before_losing_more() {
+        // Black does not take back with the queen when the knight would retake it
+        assert_eq!(see("1q2k3/8/8/4p3/8/3N1N2/8/4K3 w - - 0 1", "d3", "e5"), 85);
+        assert_eq!(see("1q2k3/8/8/4p3/8/3N4/8/4K3 w - - 0 1", "d3", "e5"), 85 - 320);
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use futures::Future;
+use futures_cpupool::{CpuFuture, CpuPool};
+
+use {Board, Color};
+use params::Params;
+use search::{Search, SearchInfo, SearchOptions, Shared, MAX_DEPTH};
+
+// Lazy SMP: every thread runs its own iterative deepening on the same root and
+// they only cooperate through the shared hash table. The main thread decides
+// the move, helpers search until it is done.
+pub struct LazySmp {
+    threads: usize,
+    params: Params,
+    options: SearchOptions,
+    shared: Arc<Shared>,
+    pool: CpuPool,
+    main: Search,
+}
+
+impl LazySmp {
+    pub fn new(threads: usize, params: Params, options: SearchOptions, pool: CpuPool) -> LazySmp {
+        let shared = Arc::new(Shared::new());
+        LazySmp {
+            threads: threads.max(1),
+            main: Search::new(0, params.clone(), options, shared.clone()),
+            params: params,
+            options: options,
+            shared: shared,
+            pool: pool,
+        }
+    }
+
+    pub fn shared(&self) -> Arc<Shared> {
+        self.shared.clone()
+    }
+
+    // Called before each search, separately from it so a stop request sent right
+    // after starting a search in the background is not lost
+    pub fn reset(&self) {
+        self.shared.stop.store(false, Ordering::SeqCst);
+        self.shared.nodes.store(0, Ordering::SeqCst);
+    }
+
+    // Forgets everything learnt from earlier searches
+    pub fn clear(&mut self) {
+        self.shared.tt.clear();
+        self.main.clear();
+    }
+
+    // The main thread's best line at each depth it completed in the last search
+    pub fn iterations(&self) -> &[SearchInfo] {
+        self.main.iterations()
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.main.set_quiet(quiet);
+    }
+
+    // The best `lines` root moves found by the main thread, each with its score
+    // and principal variation
+    pub fn search(&mut self,
+                  board: &Board,
+                  turn: Color,
+                  depth: usize,
+                  lines: usize)
+                  -> Vec<SearchInfo> {
+        let helpers = (1..self.threads)
+            .map(|id| {
+                let mut search =
+                    Search::new(id, self.params.clone(), self.options, self.shared.clone());
+                let board = *board;
+                self.pool.spawn_fn(move || -> Result<(), ()> {
+                    search.iterate(&board, turn, MAX_DEPTH - 1, 1);
+                    Ok(())
+                })
+            })
+            .collect::<Vec<CpuFuture<(), ()>>>();
+
+        let result = self.main.iterate(board, turn, depth, lines);
+        self.shared.stop.store(true, Ordering::SeqCst);
+        for helper in helpers {
+            helper.wait().expect("Failed future");
+        }
+        result
+    }
+
+    // Deepens until `time_ms` is up, returning the last iteration completed by
+    // then. During a ponder search the time only starts to run on the hit.
+    pub fn search_for(&mut self,
+                      board: &Board,
+                      turn: Color,
+                      lines: usize,
+                      time_ms: u64)
+                      -> Vec<SearchInfo> {
+        let shared = self.shared.clone();
+        shared.timed.store(true, Ordering::SeqCst);
+        let (done, finished) = mpsc::channel::<()>();
+        let timer = thread::spawn(move || {
+            let mut timeout = Err(RecvTimeoutError::Timeout);
+            while shared.pondering.load(Ordering::SeqCst) &&
+                  timeout == Err(RecvTimeoutError::Timeout) {
+                timeout = finished.recv_timeout(Duration::from_millis(5));
+            }
+            if timeout == Err(RecvTimeoutError::Timeout) {
+                timeout = finished.recv_timeout(Duration::from_millis(time_ms));
+            }
+            if timeout == Err(RecvTimeoutError::Timeout) {
+                shared.stop.store(true, Ordering::SeqCst);
+            }
+            shared.timed.store(false, Ordering::SeqCst);
+        });
+
+        let result = self.search(board, turn, MAX_DEPTH - 1, lines);
+        drop(done);
+        timer.join().expect("Failed timer");
+        result
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+
+// Sequential probability ratio test between two Elo hypotheses for the first
+// player, elo0 (H0) against elo1 (H1), with error rates alpha and beta
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    AcceptH0,
+    AcceptH1,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Verdict::AcceptH0 => write!(f, "H0 accepted"),
+            Verdict::AcceptH1 => write!(f, "H1 accepted"),
+        }
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10.0_f64.powf(-elo / 400.0))
+}
+
+// The pentanomial model counts game pairs by the points the first player took
+// from them, 0 to 2 in half points. Pairs share an opening, so this keeps the
+// correlation between their two games that counting single games would lose.
+pub type Pentanomial = [usize; 5];
+
+impl Sprt {
+    // Lower and upper log-likelihood ratio bounds
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    // Log-likelihood ratio of H1 against H0, using the normal approximation
+    // of the pair scores' distribution. Every bin gets half a pair more when
+    // estimating its variance, so the first few pairs cannot show a near zero
+    // variance and end the test early; the mean is left to the pairs played.
+    pub fn llr(&self, pairs: &Pentanomial) -> f64 {
+        let counts = pairs.iter().map(|&n| n as f64 + 0.5).collect::<Vec<f64>>();
+        let total = counts.iter().fold(0.0, |acc, n| acc + n);
+        let played = pairs.iter().sum::<usize>() as f64;
+        let scores = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+        let points = pairs.iter().zip(&scores).fold(0.0, |acc, (&n, x)| acc + n as f64 * x);
+        let mean = points / played.max(1.0);
+        let variance = counts.iter()
+            .zip(&scores)
+            .fold(0.0, |acc, (n, x)| acc + n * (x - mean).powi(2)) / total;
+
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        played * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn verdict(&self, llr: f64) -> Option<Verdict> {
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(Verdict::AcceptH1)
+        } else if llr <= lower {
+            Some(Verdict::AcceptH0)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sprt, Verdict};
+
+    fn sprt(elo0: f64, elo1: f64) -> Sprt {
+        Sprt {
+            elo0: elo0,
+            elo1: elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn bounds() {
+        let (lower, upper) = sprt(0.0, 5.0).bounds();
+        assert!(close(lower, -2.944439));
+        assert!(close(upper, 2.944439));
+    }
+
+    // The 20 pairs of [1, 3, 6, 6, 4] score 12.25, a mean of 0.6125. With half a
+    // pair added to each bin, [1.5, 3.5, 6.5, 6.5, 4.5] over 22.5 pairs spreads
+    // 1.9035156 around it, a variance of 0.0846007. Elo 0 and 5 expect 0.5 and
+    // 0.5071951, so the LLR is 20 * 0.0071951 * (1.225 - 1.0071951) / (2 * 0.0846007)
+    // = 0.1852377.
+    #[test]
+    fn llr() {
+        assert!(close(sprt(0.0, 5.0).llr(&[1, 3, 6, 6, 4]), 0.1852377));
+        // scoring exactly what H0 expects gives -20 * d^2 / (2 * var), d = s1 - s0
+        assert!(close(sprt(0.0, 10.0).llr(&[2, 4, 8, 4, 2]), -0.0256954));
+    }
+
+    #[test]
+    fn verdicts() {
+        let test = sprt(0.0, 5.0);
+        assert_eq!(test.verdict(3.0), Some(Verdict::AcceptH1));
+        assert_eq!(test.verdict(-3.0), Some(Verdict::AcceptH0));
+        assert_eq!(test.verdict(0.0), None);
+        assert_eq!(test.verdict(test.llr(&[0, 10, 200, 300, 200])), Some(Verdict::AcceptH1));
+        assert_eq!(test.verdict(test.llr(&[200, 300, 200, 10, 0])), Some(Verdict::AcceptH0));
+    }
+}
@@ -0,0 +1,509 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use {Color, Move, Piece};
+use clock::{Clock, TimeControl};
+use endgame::{self, Known, KNOWN_WIN};
+use engine::Engine;
+use eval::MATE;
+use game::{Game, GameResult, Termination};
+use params::Params;
+use pgn::{self, Replay};
+use sprt::{Pentanomial, Sprt, Verdict};
+use uci;
+
+// An engine in another process, spoken to over UCI
+pub struct UciEngine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    // the `go` command sent for every move
+    go: String,
+    score: Option<i32>,
+}
+
+impl UciEngine {
+    pub fn start(command: &str, go: String) -> Result<UciEngine, String> {
+        let mut child = Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", command, e))?;
+        let stdin = child.stdin.take().expect("Missing stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("Missing stdout"));
+
+        let mut engine = UciEngine {
+            name: command.to_string(),
+            child: child,
+            stdin: stdin,
+            stdout: stdout,
+            go: go,
+            score: None,
+        };
+        engine.send("uci")?;
+        loop {
+            let line = engine.read_line()?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            } else if line == "uciok" {
+                break;
+            }
+        }
+        engine.sync()?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command).map_err(|e| format!("{}: {}", self.name, e))
+    }
+
+    fn read_line(&mut self) -> Result<String, String> {
+        let mut line = String::new();
+        match self.stdout.read_line(&mut line) {
+            Ok(0) => Err(format!("{}: engine exited", self.name)),
+            Ok(_) => Ok(line.trim().to_string()),
+            Err(e) => Err(format!("{}: {}", self.name, e)),
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        while self.read_line()? != "readyok" {}
+        Ok(())
+    }
+
+    fn new_game(&mut self, chess960: bool) -> Result<(), String> {
+        if chess960 {
+            self.send("setoption name UCI_Chess960 value true")?;
+        }
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    // The reply to `go` in long algebraic notation, keeping the last score seen.
+    // With a clock the engine is sent both sides' times instead of its own
+    // limit, delays going as increments since UCI has nothing else for them.
+    fn best_move(&mut self, game: &Game, clock: Option<&Clock>) -> Result<String, String> {
+        self.score = None;
+        let mut position = format!("position fen {}", game.start().to_fen(false));
+        let moves = game.uci_moves();
+        if !moves.is_empty() {
+            position.push_str(&format!(" moves {}", moves.join(" ")));
+        }
+        self.send(&position)?;
+        let turn = game.turn();
+        let go = match clock {
+            Some(clock) => {
+                let mut go = format!("go wtime {} btime {} winc {} binc {}",
+                                     clock.remaining(Color::White),
+                                     clock.remaining(Color::Black),
+                                     clock.increment(Color::White),
+                                     clock.increment(Color::Black));
+                if let Some(moves) = clock.moves_to_go(turn) {
+                    go.push_str(&format!(" movestogo {}", moves));
+                }
+                go
+            }
+            None => self.go.clone(),
+        };
+        self.send(&go)?;
+
+        loop {
+            let line = self.read_line()?;
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            match tokens.first() {
+                Some(&"info") => {
+                    let score = tokens.iter().position(|&token| token == "score");
+                    let value = score.and_then(|i| tokens.get(i + 2)).and_then(|n| n.parse().ok());
+                    self.score = match (score.and_then(|i| tokens.get(i + 1)), value) {
+                        (Some(&"cp"), Some(cp)) => Some(cp),
+                        (Some(&"mate"), Some(moves)) if moves > 0 => Some(MATE - moves),
+                        (Some(&"mate"), Some(moves)) => Some(-MATE - moves),
+                        _ => self.score,
+                    };
+                }
+                Some(&"bestmove") => {
+                    return Ok(tokens.get(1).cloned().unwrap_or("0000").to_string())
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+pub enum Player {
+    Internal(String, Box<Engine>),
+    External(UciEngine),
+}
+
+impl Player {
+    pub fn name(&self) -> &str {
+        match *self {
+            Player::Internal(ref name, _) => name,
+            Player::External(ref engine) => &engine.name,
+        }
+    }
+
+    fn new_game(&mut self, chess960: bool) -> Result<(), String> {
+        match *self {
+            Player::Internal(..) => Ok(()),
+            Player::External(ref mut engine) => engine.new_game(chess960),
+        }
+    }
+
+    // The move chosen and the piece a pawn promotes to, if the player named one
+    fn next_move(&mut self,
+                 game: &Game,
+                 clock: Option<&Clock>)
+                 -> Result<Option<(Move, Option<Piece>)>, String> {
+        match *self {
+            Player::Internal(_, ref mut engine) => {
+                Ok(engine.next_move(game.board(), game.turn(), clock).map(|cmove| (cmove, None)))
+            }
+            Player::External(ref mut engine) => {
+                let token = engine.best_move(game, clock)?;
+                let promotion = uci::parse_promotion(&token);
+                Ok(uci::parse_move(&token).ok().map(|cmove| (cmove, promotion)))
+            }
+        }
+    }
+
+    // Centipawns for the player, from the search behind its last move
+    fn score(&self) -> Option<i32> {
+        match *self {
+            Player::Internal(_, ref engine) => engine.score(),
+            Player::External(ref engine) => engine.score,
+        }
+    }
+}
+
+// Start positions, from FEN or EPD lines or from the end of each PGN game,
+// whose moves are played from its FEN tag when it has one
+pub fn load_openings(path: &str) -> Result<Vec<Replay>, String> {
+    let mut openings = vec![];
+    if path.ends_with(".pgn") {
+        for game in pgn::read_games(path)? {
+            let mut replay = game.start()?;
+            for san in &game.moves {
+                if replay.play_san(san).is_err() {
+                    break;
+                }
+            }
+            openings.push(replay);
+        }
+        return Ok(openings);
+    }
+
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path, e))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fen = line.split_whitespace().take(4).collect::<Vec<&str>>().join(" ");
+        openings.push(Replay::from_fen(&fen)
+            .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?);
+    }
+    Ok(openings)
+}
+
+// Neither in check nor with a capture to make, so the material that decides a
+// known endgame will not change on the next move
+fn quiet(game: &Game) -> bool {
+    let position = game.position();
+    let turn = position.turn;
+    !position.board.in_check(turn) &&
+    !game.legal_moves().iter().any(|cmove| {
+        let (color, piece) = position.board.get(&cmove.1);
+        (color != turn && piece != Piece::Empty) || position.is_en_passant(cmove)
+    })
+}
+
+// When games are decided before they end
+#[derive(Clone, Copy, Debug)]
+pub struct Adjudication {
+    // a player whose own score stays at or below minus this many centipawns
+    // for this many of its moves loses, unless its opponent disagrees
+    pub score: Option<(i32, usize)>,
+    // endgames the evaluation knows the result of, such as KPK from its bitbase,
+    // end the game once the position is quiet
+    pub known_endgame: bool,
+}
+
+// Wins, draws and losses of the first player
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Results {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    // completed game pairs by the first player's points from them
+    pub pairs: Pentanomial,
+    pub verdict: Option<Verdict>,
+}
+
+fn elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+impl Results {
+    fn add(&mut self, score: f64) {
+        if score > 0.75 {
+            self.wins += 1;
+        } else if score < 0.25 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // Elo difference and the half width of its 95% confidence interval, from
+    // the spread of the game results. None while one side has every point.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (n, score) = (self.games() as f64, self.score());
+        if score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let variance = (self.wins as f64 * (1.0 - score).powi(2) +
+                        self.draws as f64 * (0.5 - score).powi(2) +
+                        self.losses as f64 * score.powi(2)) / n;
+        let margin = 1.96 * (variance / n).sqrt();
+        let (low, high) = ((score - margin).max(1e-6), (score + margin).min(1.0 - 1e-6));
+        Some((elo(score), (elo(high) - elo(low)) / 2.0))
+    }
+}
+
+impl fmt::Display for Results {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "+{} ={} -{}  score {:.1}%",
+               self.wins,
+               self.draws,
+               self.losses,
+               100.0 * self.score())?;
+        match self.elo() {
+            Some((elo, margin)) => write!(f, "  elo {:+.1} +/- {:.1}", elo, margin),
+            None => write!(f, "  elo n/a"),
+        }
+    }
+}
+
+// Games between two players, each opening played twice with colors reversed
+pub struct Match {
+    players: [Player; 2],
+    openings: Vec<Replay>,
+    adjudication: Adjudication,
+    // stops the match once the test decides
+    sprt: Option<Sprt>,
+    // without one, players use their own depth or `go` command
+    time_control: Option<TimeControl>,
+}
+
+impl Match {
+    pub fn new(players: [Player; 2],
+               openings: Vec<Replay>,
+               adjudication: Adjudication)
+               -> Match {
+        Match {
+            players: players,
+            openings: if openings.is_empty() {
+                vec![Replay::new()]
+            } else {
+                openings
+            },
+            adjudication: adjudication,
+            sprt: None,
+            time_control: None,
+        }
+    }
+
+    pub fn set_time_control(&mut self, time_control: TimeControl) {
+        self.time_control = Some(time_control);
+    }
+
+    pub fn set_sprt(&mut self, sprt: Sprt) {
+        self.sprt = Some(sprt);
+    }
+
+    pub fn run(&mut self, games: usize) -> Result<Results, String> {
+        let mut results = Results::default();
+        let mut first_game = 0.0;
+        for game in 0..games {
+            let opening = self.openings[game / 2 % self.openings.len()];
+            // the first player has White in the first game of each pair
+            let white = game % 2;
+            let played = self.play(white, opening)?;
+            let result = played.result.expect("Unfinished game");
+            let points = if white == 0 {
+                result.score()
+            } else {
+                1.0 - result.score()
+            };
+            results.add(points);
+
+            println!("game {}: {} vs {}  {}  ({})  {}",
+                     game + 1,
+                     self.players[white].name(),
+                     self.players[1 - white].name(),
+                     result,
+                     played.termination.expect("Unfinished game"),
+                     results);
+
+            if white == 0 {
+                first_game = points;
+                continue;
+            }
+            results.pairs[((first_game + points) * 2.0) as usize] += 1;
+            if let Some(sprt) = self.sprt {
+                let llr = sprt.llr(&results.pairs);
+                let (lower, upper) = sprt.bounds();
+                println!("pair {}: llr {:.2} ({:.2}, {:.2})  pentanomial {:?}",
+                         game / 2 + 1,
+                         llr,
+                         lower,
+                         upper,
+                         results.pairs);
+                results.verdict = sprt.verdict(llr);
+                if results.verdict.is_some() {
+                    break;
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    // The game with its result and how it ended, `white` indexing the players
+    fn play(&mut self, white: usize, opening: Replay) -> Result<Game, String> {
+        for player in &mut self.players {
+            player.new_game(opening.chess960)?;
+        }
+        let mut game = Game::from_replay(opening);
+        game.set_tag("White", self.players[white].name());
+        game.set_tag("Black", self.players[1 - white].name());
+        let mut clock = self.time_control.clone().map(Clock::new);
+        // the players may use their own, adjudication needs only one
+        let params = Params::default();
+        // by color: the last score each side gave, and for how many of its
+        // moves in a row that score has been lost
+        let mut scores = [None; 2];
+        let mut behind = [0; 2];
+
+        loop {
+            if let Some((result, termination)) = game.outcome() {
+                game.finish(result, termination);
+                return Ok(game);
+            }
+            let (board, turn) = (game.board(), game.turn());
+            if self.adjudication.known_endgame && quiet(&game) {
+                let result = match endgame::probe(&board, turn, &params) {
+                    Some(Known::Exact(score)) if score >= KNOWN_WIN => Some(GameResult::WhiteWins),
+                    Some(Known::Exact(score)) if score <= -KNOWN_WIN => Some(GameResult::BlackWins),
+                    Some(Known::Exact(_)) => Some(GameResult::Draw),
+                    _ => None,
+                };
+                if let Some(result) = result {
+                    game.finish(result, Termination::KnownEndgame);
+                    return Ok(game);
+                }
+            }
+
+            let loss = GameResult::win_for(turn.other());
+            let player = if turn == Color::White { white } else { 1 - white };
+            if let Some(ref mut clock) = clock {
+                clock.start();
+            }
+            let chosen = self.players[player].next_move(&game, clock.as_ref())?;
+            if let Some(ref mut clock) = clock {
+                if !clock.stop(turn) {
+                    game.finish(loss, Termination::Time);
+                    return Ok(game);
+                }
+            }
+            let (cmove, promotion) = match chosen {
+                Some(cmove) => cmove,
+                None => {
+                    game.finish(loss, Termination::NoMove);
+                    return Ok(game);
+                }
+            };
+            let score = self.players[player].score();
+            match game.play(cmove, promotion) {
+                Ok(record) => {
+                    record.eval = score;
+                    record.clock_ms = clock.as_ref().map(|clock| clock.remaining(turn));
+                }
+                Err(_) => {
+                    game.finish(loss, Termination::IllegalMove);
+                    return Ok(game);
+                }
+            }
+
+            if let Some((threshold, moves)) = self.adjudication.score {
+                let side = turn as usize;
+                scores[side] = score;
+                behind[side] = match scores[side] {
+                    Some(score) if score <= -threshold => behind[side] + 1,
+                    _ => 0,
+                };
+                if behind[side] >= moves && scores[1 - side].map_or(true, |s| s >= threshold) {
+                    game.finish(loss, Termination::Adjudicated);
+                    return Ok(game);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+
+    use super::{load_openings, quiet};
+    use game::Game;
+    use pgn::Replay;
+
+    #[test]
+    fn openings_from_a_fen_tag() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let path = env::temp_dir().join("chess-openings-fen-tag.pgn");
+        let mut file = File::create(&path).unwrap();
+        write!(file, "[FEN \"{}\"]\n[SetUp \"1\"]\n\n1. e4 Kd7 *\n", fen).unwrap();
+
+        let openings = load_openings(path.to_str().unwrap()).unwrap();
+        let mut expected = Replay::from_fen(fen).unwrap();
+        expected.play_san("e4").unwrap();
+        expected.play_san("Kd7").unwrap();
+        assert_eq!(openings.len(), 1);
+        assert_eq!(openings[0].key(), expected.key());
+    }
+
+    #[test]
+    fn quiet_positions() {
+        let quiet_fen = |fen| quiet(&Game::from_replay(Replay::from_fen(fen).unwrap()));
+        assert!(quiet_fen("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1"));
+        // the pawn can be taken
+        assert!(!quiet_fen("8/8/8/8/8/3k4/4P3/4K3 b - - 0 1"));
+        // in check
+        assert!(!quiet_fen("4R3/8/8/8/8/4k3/8/K7 b - - 0 1"));
+        assert!(!quiet_fen("8/8/8/3k4/3pP3/8/8/4K3 b - e3 0 1"));
+    }
+}
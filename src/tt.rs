@@ -0,0 +1,77 @@
+use std::sync::Mutex;
+
+use Move;
+
+// Entries are spread over this many independently locked shards so threads
+// sharing the table rarely wait on each other
+const SHARDS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    key: u64,
+    pub best_move: Option<Move>,
+    pub depth: usize,
+    pub score: i32,
+    pub bound: Bound,
+}
+
+pub struct TranspositionTable {
+    shards: Vec<Mutex<Vec<Option<Entry>>>>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(size: usize) -> TranspositionTable {
+        let size = size.next_power_of_two().max(SHARDS);
+        TranspositionTable {
+            shards: (0..SHARDS).map(|_| Mutex::new(vec![None; size / SHARDS])).collect(),
+            mask: size - 1,
+        }
+    }
+
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            for entry in shard.lock().unwrap().iter_mut() {
+                *entry = None;
+            }
+        }
+    }
+
+    fn locate(&self, key: u64) -> (usize, usize) {
+        let index = key as usize & self.mask;
+        (index % SHARDS, index / SHARDS)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let (shard, index) = self.locate(key);
+        match self.shards[shard].lock().unwrap()[index] {
+            Some(entry) if entry.key == key => Some(entry),
+            _ => None,
+        }
+    }
+
+    // Keeps the deeper result for the same position, otherwise always replaces
+    pub fn store(&self, key: u64, best_move: Option<Move>, depth: usize, score: i32, bound: Bound) {
+        let (shard, index) = self.locate(key);
+        let mut entries = self.shards[shard].lock().unwrap();
+        if let Some(entry) = entries[index] {
+            if entry.key == key && entry.depth > depth {
+                return;
+            }
+        }
+        entries[index] = Some(Entry {
+            key: key,
+            best_move: best_move,
+            depth: depth,
+            score: score,
+            bound: bound,
+        });
+    }
+}
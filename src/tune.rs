@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+use futures::Future;
+use futures_cpupool::CpuPool;
+
+use {Board, Color};
+use eval::Evaluator;
+use params::Params;
+
+const CHUNKS: usize = 16;
+
+// Accepts `1-0`, `0-1`, `1/2-1/2` or a score between 0 and 1, optionally
+// wrapped in quotes or brackets as found in EPD and tuning data sets
+fn parse_result(token: &str) -> Option<f64> {
+    let token = token.trim_matches(|c| c == '"' || c == '[' || c == ']' || c == ';');
+    match token {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => {
+            token.parse::<f64>()
+                .ok()
+                .and_then(|r| if 0.0 <= r && r <= 1.0 { Some(r) } else { None })
+        }
+    }
+}
+
+// One position per line: a FEN followed by the game result from White's point of view
+pub fn load_positions(path: &str) -> Result<Vec<(Board, Color, f64)>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut positions = vec![];
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path, e))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = match line.split_whitespace().last().and_then(parse_result) {
+            Some(result) => result,
+            None => return Err(format!("{}:{}: missing game result", path, n + 1)),
+        };
+        let fen = line.split([';', '[', '"', '|'])
+            .next()
+            .unwrap_or("");
+        let fen = fen.split_whitespace().take(6).collect::<Vec<&str>>().join(" ");
+        match Board::from_fen(&fen) {
+            Ok((board, turn)) => positions.push((board, turn, result)),
+            Err(err) => return Err(format!("{}:{}: {}", path, n + 1, err)),
+        }
+    }
+    Ok(positions)
+}
+
+fn sigmoid(score: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10.0_f64.powf(-k * score as f64 / 400.0))
+}
+
+pub struct Tuner {
+    positions: Arc<Vec<(Board, Color, f64)>>,
+    pool: CpuPool,
+    k: f64,
+}
+
+impl Tuner {
+    // The error is a mean over the positions, so there must be some
+    pub fn new(positions: Vec<(Board, Color, f64)>, pool: CpuPool) -> Result<Tuner, String> {
+        if positions.is_empty() {
+            return Err("no positions to tune on".to_string());
+        }
+        Ok(Tuner {
+            positions: Arc::new(positions),
+            pool: pool,
+            k: 1.0,
+        })
+    }
+
+    pub fn error(&self, params: &Params) -> f64 {
+        let chunk_size = self.positions.len() / CHUNKS + 1;
+        let mut futures = vec![];
+
+        for start in (0..self.positions.len()).filter(|i| i % chunk_size == 0) {
+            let positions = self.positions.clone();
+            let params = params.clone();
+            let k = self.k;
+
+            futures.push(self.pool.spawn_fn(move || -> Result<f64, ()> {
+                let mut evaluator = Evaluator::with_params(params);
+                let end = (start + chunk_size).min(positions.len());
+                Ok(positions[start..end]
+                    .iter()
+                    .map(|&(ref board, turn, result)| {
+                        let error = result - sigmoid(evaluator.evaluate(board, turn), k);
+                        error * error
+                    })
+                    .sum())
+            }));
+        }
+
+        let sum = futures.into_iter()
+            .map(|future| future.wait().expect("Failed future"))
+            .fold(0.0, |acc, error| acc + error);
+        sum / self.positions.len() as f64
+    }
+
+    // Picks the sigmoid scaling that best fits the untuned evaluation
+    pub fn fit_k(&mut self, params: &Params) -> f64 {
+        let mut best = (self.k, self.error(params));
+        for step in 1..61 {
+            self.k = step as f64 * 0.05;
+            let error = self.error(params);
+            if error < best.1 {
+                best = (self.k, error);
+            }
+        }
+        self.k = best.0;
+        self.k
+    }
+
+    // Local search: nudge every parameter by one in each direction and keep the
+    // change whenever it lowers the error, until a full pass finds nothing
+    pub fn tune(&self, params: &mut Params, iterations: usize, out: &str) -> Result<f64, String> {
+        let mut best = self.error(params);
+        println!("initial error: {:.6}", best);
+
+        let count = params.count();
+        for iteration in 0..iterations {
+            let mut improved = false;
+
+            for index in 0..count {
+                for &delta in &[1, -1] {
+                    params.adjust(index, delta);
+                    let error = self.error(params);
+                    if error < best {
+                        best = error;
+                        improved = true;
+                        break;
+                    }
+                    params.adjust(index, -delta);
+                }
+            }
+
+            println!("iteration {}: error {:.6}", iteration + 1, best);
+            params.save(out)?;
+            if !improved {
+                break;
+            }
+        }
+
+        Ok(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_cpupool::CpuPool;
+
+    use super::{parse_result, Tuner};
+
+    #[test]
+    fn results() {
+        assert_eq!(parse_result("1-0"), Some(1.0));
+        assert_eq!(parse_result("\"1/2-1/2\";"), Some(0.5));
+        assert_eq!(parse_result("[0.25]"), Some(0.25));
+        assert_eq!(parse_result("2"), None);
+    }
+
+    #[test]
+    fn no_positions() {
+        assert!(Tuner::new(vec![], CpuPool::new(1)).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use futures_cpupool::CpuPool;
+
+use {move_string, Board, Color, Move, Piece, Square, FILES, RANKS};
+use clock;
+use engine::EngineConfig;
+use game::Game;
+use pgn::Replay;
+use search::{Shared, MAX_DEPTH};
+use smp::LazySmp;
+
+pub fn parse_square(token: &str) -> Option<Square> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next().and_then(|c| c.to_digit(10))) {
+        (Some(file), Some(rank)) if FILES.contains(&file) && RANKS.contains(&(rank as u8)) => {
+            Some(Square::new(file, rank as u8))
+        }
+        _ => None,
+    }
+}
+
+// Long algebraic notation, see `parse_promotion` for the suffix
+pub fn parse_move(token: &str) -> Result<Move, String> {
+    if token.len() < 4 || token.len() > 5 {
+        return Err(format!("invalid move '{}'", token));
+    }
+    match (parse_square(&token[0..2]), parse_square(&token[2..4])) {
+        (Some(from), Some(to)) => Ok((from, to)),
+        _ => Err(format!("invalid move '{}'", token)),
+    }
+}
+
+// The piece named by a promotion suffix
+pub fn parse_promotion(token: &str) -> Option<Piece> {
+    match token.chars().nth(4) {
+        Some('n') => Some(Piece::Knight),
+        Some('b') => Some(Piece::Bishop),
+        Some('r') => Some(Piece::Rook),
+        Some('q') => Some(Piece::Queen),
+        _ => None,
+    }
+}
+
+// `startpos` or `fen <fields>`, optionally followed by `moves <move>...`. In
+// Chess960 castling is only written as the king taking its rook.
+fn parse_position(args: &[&str], chess960: bool) -> Result<(Board, Color), String> {
+    let moves = args.iter().position(|&arg| arg == "moves").unwrap_or(args.len());
+    let mut start = match args.first() {
+        Some(&"startpos") => Replay::new(),
+        Some(&"fen") => Replay::from_fen(&args[1..moves].join(" "))?,
+        _ => return Err("expected 'startpos' or 'fen'".to_string()),
+    };
+    start.chess960 = start.chess960 || chess960;
+    let mut game = Game::from_replay(start);
+
+    for token in args.iter().skip(moves + 1) {
+        game.play(parse_move(token)?, parse_promotion(token))?;
+    }
+    Ok((game.board(), game.turn()))
+}
+
+struct Uci {
+    config: EngineConfig,
+    board: Board,
+    turn: Color,
+    chess960: bool,
+    smp: Option<LazySmp>,
+    pool: CpuPool,
+    shared: Arc<Shared>,
+    search: Option<JoinHandle<LazySmp>>,
+}
+
+impl Uci {
+    fn new(config: EngineConfig) -> Uci {
+        let pool = CpuPool::new_num_cpus();
+        let smp = LazySmp::new(config.threads, config.params.clone(), config.search, pool.clone());
+        Uci {
+            config: config,
+            board: Board::new(),
+            turn: Color::White,
+            chess960: false,
+            shared: smp.shared(),
+            smp: Some(smp),
+            pool: pool,
+            search: None,
+        }
+    }
+
+    // Drops the hash table, used for a new game or a different thread count
+    fn restart(&mut self) {
+        self.stop();
+        let smp = LazySmp::new(self.config.threads,
+                               self.config.params.clone(),
+                               self.config.search,
+                               self.pool.clone());
+        self.shared = smp.shared();
+        self.smp = Some(smp);
+    }
+
+    fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.smp = Some(search.join().expect("Search thread panicked"));
+        }
+    }
+
+    // On a ponder miss the GUI sends `stop`, the answer it then gets is ignored
+    fn stop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        self.shared.pondering.store(false, Ordering::SeqCst);
+        self.wait();
+    }
+
+    // `go infinite` is a ponder search that never gets a hit and has no time
+    // limit. `go ponder` keeps its time budget, which starts on `ponderhit`.
+    fn go(&mut self, args: &[&str]) {
+        self.wait();
+        let depth = args.iter()
+            .position(|&arg| arg == "depth")
+            .and_then(|i| args.get(i + 1))
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(self.config.depth)
+            .min(MAX_DEPTH - 1);
+        let infinite = args.contains(&"infinite");
+        let ponder = args.contains(&"ponder") || infinite;
+        let value = |name| {
+            args.iter()
+                .position(|&arg| arg == name)
+                .and_then(|i| args.get(i + 1))
+                .and_then(|n| n.parse::<u64>().ok())
+        };
+        let (time, increment) = match self.turn {
+            Color::White => (value("wtime"), value("winc")),
+            Color::Black => (value("btime"), value("binc")),
+        };
+        // `movetime`, or a share of the clock, limits the search
+        let time_ms = if infinite {
+            None
+        } else {
+            value("movetime").or_else(|| {
+                time.map(|time| {
+                    clock::budget(time,
+                                  increment.unwrap_or(0),
+                                  value("movestogo").map(|moves| moves as usize))
+                })
+            })
+        };
+
+        let mut smp = self.smp.take().expect("Search already running");
+        smp.reset();
+        self.shared.pondering.store(ponder, Ordering::SeqCst);
+
+        let shared = self.shared.clone();
+        let board = self.board;
+        let turn = self.turn;
+        let lines = self.config.lines;
+        self.search = Some(thread::spawn(move || {
+            let result = match time_ms {
+                Some(time_ms) => smp.search_for(&board, turn, lines, time_ms),
+                None => smp.search(&board, turn, depth, lines),
+            };
+            let pv = result.first()
+                .map_or(vec![], |line| line.pv.clone());
+            // The answer to a ponder search must wait for `ponderhit` or `stop`
+            while shared.pondering.load(Ordering::SeqCst) && !shared.stop.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            match (pv.first(), pv.get(1)) {
+                (Some(best), Some(reply)) => {
+                    println!("bestmove {} ponder {}", move_string(best), move_string(reply))
+                }
+                (Some(best), None) => println!("bestmove {}", move_string(best)),
+                _ => println!("bestmove 0000"),
+            }
+            smp
+        }));
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        let value = args.iter().position(|&arg| arg == "value").unwrap_or(args.len());
+        let name = args.iter().skip(1).take(value - 1).cloned().collect::<Vec<&str>>().join(" ");
+        let value = args.iter().skip(value + 1).cloned().collect::<Vec<&str>>().join(" ");
+
+        match name.as_str() {
+            "Threads" => {
+                match value.parse() {
+                    Ok(threads) => {
+                        self.config.threads = threads;
+                        self.restart();
+                    }
+                    Err(_) => println!("info string invalid thread count '{}'", value),
+                }
+            }
+            "MultiPV" => {
+                match value.parse() {
+                    Ok(lines) => self.config.lines = lines,
+                    Err(_) => println!("info string invalid line count '{}'", value),
+                }
+            }
+            // Pondering is driven by `go ponder`, the option only tells us the GUI allows it
+            "Ponder" => (),
+            "UCI_Chess960" => self.chess960 = value == "true",
+            _ => println!("info string unknown option '{}'", name),
+        }
+    }
+
+    // Returns false once the GUI asks to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name chess");
+                println!("id author angelini");
+                println!("option name Threads type spin default {} min 1 max 256",
+                         self.config.threads);
+                println!("option name MultiPV type spin default {} min 1 max 256",
+                         self.config.lines);
+                println!("option name Ponder type check default false");
+                println!("option name UCI_Chess960 type check default false");
+                println!("uciok");
+            }
+            Some(&"isready") => println!("readyok"),
+            Some(&"ucinewgame") => self.restart(),
+            Some(&"setoption") => self.set_option(&tokens[1..]),
+            Some(&"position") => {
+                self.stop();
+                match parse_position(&tokens[1..], self.chess960) {
+                    Ok((board, turn)) => {
+                        self.board = board;
+                        self.turn = turn;
+                    }
+                    Err(err) => println!("info string {}", err),
+                }
+            }
+            Some(&"go") => self.go(&tokens[1..]),
+            Some(&"ponderhit") => self.shared.pondering.store(false, Ordering::SeqCst),
+            Some(&"stop") => self.stop(),
+            Some(&"quit") => {
+                self.stop();
+                return false;
+            }
+            _ => (),
+        }
+        true
+    }
+}
+
+pub fn run(config: EngineConfig) {
+    let mut uci = Uci::new(config);
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(line) => {
+                if !uci.handle(&line) {
+                    return;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    uci.stop();
+}